@@ -0,0 +1,31 @@
+//! Clean a URL offline, when there is something to remove and when it's already clean.
+
+use clearurl::UrlCleaner;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const RULES: &str = r#"
+[settings]
+default_is_global = true
+
+["default"]
+ban = ["^utm_[a-z_]+", "(?:%3F)?fbclid", "(?:%3F)?gclid"]
+
+["example.com"]
+ban = ["^spm(?:_id)?", "share_[a-z]+", "from.*"]
+"#;
+
+fn bench_clear(c: &mut Criterion) {
+    let cleaner = UrlCleaner::from_toml(RULES).unwrap();
+    let clean = "https://example.com/watch?v=dQw4w9WgXcQ&t=30&list=PL1&index=2";
+    let dirty = "https://example.com/watch?v=dQw4w9WgXcQ&t=30&utm_source=a&spm_id=1";
+
+    c.bench_function("already clean", |b| {
+        b.iter(|| cleaner.try_clear_offline(clean).unwrap())
+    });
+    c.bench_function("params removed", |b| {
+        b.iter(|| cleaner.try_clear_offline(dirty).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_clear);
+criterion_main!(benches);
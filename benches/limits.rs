@@ -0,0 +1,46 @@
+//! Clean a 100 KB data-stuffed URL, with and without the default limits.
+
+use clearurl::{Limits, UrlCleaner};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const RULES: &str = r#"
+["example.com"]
+ban = ["^utm_[a-z_]+", "(?:%3F)?fbclid", "share_[a-z]+", "from.*"]
+"#;
+
+fn stuffed_url() -> String {
+    let blob = "QUJD".repeat(25);
+    let params: Vec<String> = (0..1000).map(|i| format!("d{i}={blob}")).collect();
+    format!("https://example.com/?utm_source=a&{}", params.join("&"))
+}
+
+fn bench_limits(c: &mut Criterion) {
+    let url = stuffed_url();
+    let bounded = UrlCleaner::builder()
+        .rules_from_toml(RULES)
+        .limits(Limits {
+            max_url_len: None,
+            ..Limits::default()
+        })
+        .build()
+        .unwrap();
+    let unbounded = UrlCleaner::builder()
+        .rules_from_toml(RULES)
+        .limits(Limits::none())
+        .build()
+        .unwrap();
+
+    c.bench_function("100 KB URL, 256 params examined", |b| {
+        b.iter(|| bounded.clear_offline(&url).unwrap())
+    });
+    c.bench_function("100 KB URL, unlimited", |b| {
+        b.iter(|| unbounded.clear_offline(&url).unwrap())
+    });
+    c.bench_function("100 KB URL, rejected by length", |b| {
+        let rejecting = UrlCleaner::from_toml(RULES).unwrap();
+        b.iter(|| rejecting.clear_offline(&url).unwrap_err())
+    });
+}
+
+criterion_group!(benches, bench_limits);
+criterion_main!(benches);
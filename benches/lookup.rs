@@ -0,0 +1,40 @@
+//! Look the rule of a URL up in a synthetic ruleset of 1000 domains and 50 URL patterns, by
+//! host, by pattern and falling back to the default rule.
+
+use clearurl::UrlCleaner;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+fn ruleset(domains: usize, patterns: usize) -> String {
+    let mut rules = String::from("[\"default\"]\nban = [\"^utm_[a-z_]+\"]\n");
+    for i in 0..domains {
+        rules.push_str(&format!(
+            "[\"site{i}.example\"]\nsub = [\"www\", \"m\"]\nban = [\"^spm(?:_id)?\", \"param{i}\"]\n"
+        ));
+    }
+    for i in 0..patterns {
+        rules.push_str(&format!(
+            "[pattern.'^https?://[^/]+/share{i}/']\nban = [\"from\"]\n"
+        ));
+    }
+    rules
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let cleaner = UrlCleaner::from_toml(&ruleset(1000, 50)).unwrap();
+    let by_host = "https://m.site500.example/item?id=1&spm_id=2";
+    let by_pattern = "https://other.test/share49/item?id=1&from=feed";
+    let by_default = "https://other.test/item?id=1&utm_source=feed";
+
+    c.bench_function("lookup by host", |b| {
+        b.iter(|| cleaner.try_clear_offline(by_host).unwrap())
+    });
+    c.bench_function("lookup by last pattern", |b| {
+        b.iter(|| cleaner.try_clear_offline(by_pattern).unwrap())
+    });
+    c.bench_function("lookup falling back to default", |b| {
+        b.iter(|| cleaner.try_clear_offline(by_default).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_lookup);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+//! Parse a synthetic ruleset where every domain repeats the same tracking patterns.
+
+use clearurl::UrlCleaner;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+fn ruleset(domains: usize) -> String {
+    (0..domains)
+        .map(|i| {
+            format!(
+                r#"
+["site{i}.example"]
+sub = ["www", "m"]
+ban = ["^utm_[a-z_]+", "(?:%3F)?fbclid", "(?:%3F)?gclid", "^spm(?:_id)?", "share_[a-z]+", "from.*", "param{i}"]
+"#
+            )
+        })
+        .collect()
+}
+
+fn bench_startup(c: &mut Criterion) {
+    let rules = ruleset(500);
+    c.bench_function("parse 500 domains", |b| {
+        b.iter(|| UrlCleaner::from_toml(&rules).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_startup);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+//! Clean URLs through a tower stack, at most 4 at once.
+//!
+//! ```sh
+//! cargo run --example tower --features tower -- 'https://b23.tv/C0lw13z'
+//! ```
+
+use std::sync::Arc;
+
+use clearurl::{CleanService, UrlCleaner};
+use futures_util::{stream, StreamExt};
+use tower::{limit::ConcurrencyLimit, ServiceExt};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let cleaner = UrlCleaner::from_file("./rules.toml").unwrap();
+    let service = ConcurrencyLimit::new(CleanService::new(Arc::new(cleaner)), 4);
+
+    // every URL is sent as soon as the limit lets it, outcomes come back in order
+    let urls: Vec<String> = std::env::args().skip(1).collect();
+    let outcomes = service.call_all(stream::iter(urls.clone()));
+    let mut outcomes = std::pin::pin!(outcomes);
+    for url in urls {
+        match outcomes.next().await.unwrap() {
+            Ok(outcome) => println!("{url} -> {}", outcome.into_url()),
+            Err(err) => println!("{url}: {err}"),
+        }
+    }
+}
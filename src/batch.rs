@@ -0,0 +1,202 @@
+//! Clean many URLs at once and summarize how it went.
+
+use std::collections::HashMap;
+
+#[cfg(feature = "http")]
+use std::time::Duration;
+
+#[cfg(feature = "http")]
+use futures_util::StreamExt;
+
+#[cfg(feature = "http")]
+use crate::UrlCleaner;
+use crate::{ClearOutcome, UrlCleanError};
+
+/// The results of a batch with their summary, returned by
+/// [`UrlCleaner::clear_all_summarized`]. It can also be collected from the results of any
+/// other API, like the ones of [`UrlCleaner::try_clear_offline`].
+#[derive(Debug, Default)]
+pub struct BatchOutcome {
+    /// Each input with its result, in input order.
+    pub results: Vec<(String, Result<ClearOutcome, UrlCleanError>)>,
+    pub summary: BatchSummary,
+}
+
+/// Counts of a [`BatchOutcome`]. Every input is counted in exactly one of `cleaned`,
+/// `already_clean`, `skipped`, `deadline_exceeded` and `failed`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub cleaned: u32,
+    pub already_clean: u32,
+    /// Inputs that are not cleaned on purpose: relative URLs, unsupported schemes and
+    /// hosts cooling down.
+    pub skipped: u32,
+    /// Inputs given up with [`UrlCleanError::DeadlineExceeded`], which still carry the
+    /// input cleaned without network access.
+    pub deadline_exceeded: u32,
+    pub failed: u32,
+    /// Errors of the skipped and failed inputs by [`UrlCleanError::kind`].
+    pub errors_by_kind: HashMap<&'static str, u32>,
+}
+
+impl BatchSummary {
+    fn record(&mut self, result: &Result<ClearOutcome, UrlCleanError>) {
+        let err = match result {
+            Ok(ClearOutcome::Cleaned(_)) => {
+                self.cleaned += 1;
+                return;
+            }
+            Ok(ClearOutcome::AlreadyClean(_)) => {
+                self.already_clean += 1;
+                return;
+            }
+            Err(err) => err,
+        };
+        match err {
+            UrlCleanError::RelativeUrl | UrlCleanError::UnsupportedScheme(_) => self.skipped += 1,
+            #[cfg(feature = "http")]
+            UrlCleanError::HostCoolingDown { .. } => self.skipped += 1,
+            UrlCleanError::DeadlineExceeded { .. } => self.deadline_exceeded += 1,
+            _ => self.failed += 1,
+        }
+        *self.errors_by_kind.entry(err.kind()).or_default() += 1;
+    }
+}
+
+impl FromIterator<(String, Result<ClearOutcome, UrlCleanError>)> for BatchOutcome {
+    fn from_iter<I: IntoIterator<Item = (String, Result<ClearOutcome, UrlCleanError>)>>(
+        iter: I,
+    ) -> Self {
+        let mut outcome = Self::default();
+        for (url, result) in iter {
+            outcome.summary.record(&result);
+            outcome.results.push((url, result));
+        }
+        outcome
+    }
+}
+
+#[cfg(feature = "http")]
+impl UrlCleaner {
+    /// Clean every URL like [`UrlCleaner::try_clear`], at most `concurrency` at once, and
+    /// summarize the results. With a `deadline`, each URL is given up on like in
+    /// [`UrlCleaner::clear_with_deadline`] when it takes longer.
+    pub async fn clear_all_summarized(
+        &self,
+        urls: impl IntoIterator<Item = String>,
+        concurrency: usize,
+        deadline: Option<Duration>,
+    ) -> BatchOutcome {
+        futures_util::stream::iter(urls)
+            .map(|url| async move {
+                let result = match deadline {
+                    Some(deadline) => {
+                        (self.within_deadline(&url, deadline, self.try_clear(&url))).await
+                    }
+                    None => self.try_clear(&url).await,
+                };
+                (url, result)
+            })
+            .buffered(concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(feature = "http")]
+#[tokio::test]
+async fn test_clear_all_summarized() {
+    use crate::test_support::MockShortener;
+
+    let shortener = MockShortener::new().await;
+    for path in ["/slow", "/slow?utm_source=a"] {
+        shortener
+            .map(path, "https://example.com/")
+            .latency(path, Duration::from_secs(2));
+    }
+    // a client giving up on the slow redirection, reaching the shortener as its own does
+    let client = reqwest::Client::builder()
+        .no_proxy()
+        .resolve(shortener.host(), shortener.addr())
+        .timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let cleaner = UrlCleaner::builder()
+        .rules_from_toml(
+            "[\"example.com\"]\nban = [\"utm_source\"]\n\n[\"short.test\"]\nredirect = true\n",
+        )
+        .http_client(client)
+        .build()
+        .unwrap();
+
+    let urls = [
+        "https://example.com/?utm_source=a&id=1".to_string(),
+        "https://example.com/?id=2".to_string(),
+        "https://example.com/?utm_source=b".to_string(),
+        "https://[::1/".to_string(),
+        "ftp://example.com/file".to_string(),
+        shortener.url("/slow"),
+    ];
+    let outcome = cleaner.clear_all_summarized(urls.clone(), 4, None).await;
+
+    let inputs: Vec<_> = outcome.results.iter().map(|(url, _)| url.clone()).collect();
+    assert_eq!(inputs, urls);
+    assert_eq!(
+        outcome.summary,
+        BatchSummary {
+            cleaned: 2,
+            already_clean: 1,
+            skipped: 1,
+            deadline_exceeded: 0,
+            failed: 2,
+            errors_by_kind: HashMap::from([
+                ("UrlParseError", 1),
+                ("UnsupportedScheme", 1),
+                ("RedirectFail", 1),
+            ]),
+        }
+    );
+    assert!(matches!(
+        outcome.results[5].1,
+        Err(UrlCleanError::RedirectFail(_))
+    ));
+
+    // the slow redirection is given up on before the client times out
+    let urls = [
+        shortener.url("/slow?utm_source=a"),
+        "https://example.com/?utm_source=a&id=1".to_string(),
+    ];
+    let outcome = cleaner
+        .clear_all_summarized(urls.clone(), 4, Some(Duration::from_millis(50)))
+        .await;
+    assert_eq!(
+        outcome.summary,
+        BatchSummary {
+            cleaned: 1,
+            deadline_exceeded: 1,
+            errors_by_kind: HashMap::from([("DeadlineExceeded", 1)]),
+            ..Default::default()
+        }
+    );
+    match &outcome.results[0].1 {
+        Err(UrlCleanError::DeadlineExceeded { partial }) => {
+            assert_eq!(partial.as_str(), urls[0]);
+        }
+        otherwise => panic!("deadline should be exceeded, got {otherwise:?}"),
+    }
+}
+
+#[test]
+fn test_collect_offline() {
+    let cleaner =
+        crate::UrlCleaner::from_toml("[\"example.com\"]\nban = [\"utm_source\"]\n").unwrap();
+    let outcome: BatchOutcome = ["https://example.com/?utm_source=a", "/relative"]
+        .into_iter()
+        .map(|url| (url.to_string(), cleaner.try_clear_offline(url)))
+        .collect();
+    assert_eq!(outcome.summary.cleaned, 1);
+    assert_eq!(outcome.summary.skipped, 1);
+    assert_eq!(outcome.summary.errors_by_kind["RelativeUrl"], 1);
+}
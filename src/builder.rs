@@ -0,0 +1,416 @@
+#[cfg(feature = "remote_rules")]
+use std::path::Path;
+use std::{path::PathBuf, sync::Arc};
+
+use arc_swap::ArcSwap;
+
+#[cfg(feature = "remote_rules")]
+use crate::remote::{Remote, RemotePolicy};
+#[cfg(feature = "http")]
+use crate::{cooldown, RedirectCredentials};
+use crate::{
+    event::{CleanEvent, Observer},
+    limits::Limits,
+    normalize::Normalization,
+    preset::{self, Preset},
+    rules::{self, OriginPolicy, RuleParseError, RuleWarning},
+    Options, ReportDetail, UrlCleaner,
+};
+
+/// Where the ruleset comes from.
+enum RuleSource {
+    File(PathBuf),
+    Toml(String),
+    Presets(Vec<Preset>),
+}
+
+/// Builder for [`UrlCleaner`], created by [`UrlCleaner::builder`].
+pub struct UrlCleanerBuilder {
+    rules: Option<RuleSource>,
+    #[cfg(feature = "remote_rules")]
+    remote: Option<(url::Url, RemotePolicy)>,
+    parse_options: rules::ParseOptions,
+    strict: bool,
+    #[cfg(feature = "http")]
+    http_client: Option<reqwest::Client>,
+    normalization: Normalization,
+    limits: Limits,
+    deny_value_hosts: Vec<String>,
+    semicolon_separator: bool,
+    rewrite_semicolon_separator: bool,
+    flag_suspicious: bool,
+    report_detail: ReportDetail,
+    reduced_to_origin: OriginPolicy,
+    allowed_schemes: Vec<String>,
+    intent_links: bool,
+    #[cfg(feature = "http")]
+    redirect_credentials: RedirectCredentials,
+    #[cfg(feature = "http")]
+    cooldown_capacity: usize,
+    #[cfg(feature = "http")]
+    detect_cloaking: bool,
+    #[cfg(feature = "http")]
+    concurrency: usize,
+    observer: Option<Observer>,
+    #[cfg(feature = "hooks-core")]
+    disabled_hooks: Vec<String>,
+}
+
+impl Default for UrlCleanerBuilder {
+    fn default() -> Self {
+        Self {
+            rules: None,
+            #[cfg(feature = "remote_rules")]
+            remote: None,
+            parse_options: rules::ParseOptions::default(),
+            strict: false,
+            #[cfg(feature = "http")]
+            http_client: None,
+            normalization: Normalization::default(),
+            limits: Limits::default(),
+            deny_value_hosts: Vec::new(),
+            semicolon_separator: false,
+            rewrite_semicolon_separator: false,
+            flag_suspicious: false,
+            report_detail: ReportDetail::default(),
+            reduced_to_origin: OriginPolicy::default(),
+            // only web URLs are processed unless user explicitly allow more
+            allowed_schemes: vec!["http".to_string(), "https".to_string()],
+            intent_links: false,
+            #[cfg(feature = "http")]
+            redirect_credentials: RedirectCredentials::default(),
+            #[cfg(feature = "http")]
+            cooldown_capacity: 1024,
+            #[cfg(feature = "http")]
+            detect_cloaking: false,
+            #[cfg(feature = "http")]
+            concurrency: 8,
+            observer: None,
+            #[cfg(feature = "hooks-core")]
+            disabled_hooks: Vec::new(),
+        }
+    }
+}
+
+impl UrlCleanerBuilder {
+    /// Read rules from the given toml file. The file is read and parsed in
+    /// [`UrlCleanerBuilder::build`].
+    pub fn rules_from_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.rules = Some(RuleSource::File(path.into()));
+        self
+    }
+
+    /// Read rules from the given toml document. The document is parsed in
+    /// [`UrlCleanerBuilder::build`].
+    pub fn rules_from_toml(mut self, data: &str) -> Self {
+        self.rules = Some(RuleSource::Toml(data.to_string()));
+        self
+    }
+
+    /// Use the given packs of the bundled ruleset, in addition to the default rule. See
+    /// [`UrlCleaner::with_presets`].
+    pub fn presets(mut self, presets: &[Preset]) -> Self {
+        self.rules = Some(RuleSource::Presets(presets.to_vec()));
+        self
+    }
+
+    #[cfg(feature = "remote_rules")]
+    /// Fetch the base ruleset from the given URL, and merge the local rules set with the
+    /// other methods over it, so a local entry overwrites the remote one for the same
+    /// domain. The remote ruleset is fetched by [`UrlCleanerBuilder::build_async`], then
+    /// refreshed in the background with `If-None-Match` as the policy says. The last good
+    /// copy is cached and used when fetching fails or the copy is invalid.
+    ///
+    /// Changes made with [`UrlCleaner::extend_default_ban`] are lost when the remote
+    /// ruleset changes.
+    pub fn remote_rules(mut self, url: url::Url, policy: RemotePolicy) -> Self {
+        self.remote = Some((url, policy));
+        self
+    }
+
+    /// Restore the old `sub` semantics: only the listed subdomains are registered, the base
+    /// domain itself falls through to the default rule.
+    pub fn legacy_sub_semantics(mut self, enable: bool) -> Self {
+        self.parse_options.register_base_with_sub = !enable;
+        self
+    }
+
+    /// Treat ruleset warnings, like an entry overwriting the domain of a previous entry,
+    /// as fatal in [`UrlCleanerBuilder::build`].
+    pub fn strict(mut self, enable: bool) -> Self {
+        self.strict = enable;
+        self
+    }
+
+    /// Skip the invalid entries and ban patterns of the ruleset instead of failing, keeping
+    /// the rest. Each skipped item is listed in [`UrlCleaner::warnings`], so combined with
+    /// [`UrlCleanerBuilder::strict`] they are fatal again. TOML syntax errors are always
+    /// fatal.
+    pub fn lenient(mut self, enable: bool) -> Self {
+        self.parse_options.lenient = enable;
+        self
+    }
+
+    #[cfg(feature = "http")]
+    /// Use the given HTTP client for redirection and network hooks. A default client is
+    /// created when unset, and another one not following redirections for network hooks.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Apply the given normalization to every cleaned URL.
+    pub fn normalize(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Bound the work spent on a single URL. See [`Limits`] for the defaults.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Remove query parameters whose value embeds an `http`/`https` URL of the given hosts or
+    /// their subdomains, like `doubleclick.net`, even if the key is not banned or is kept by
+    /// the rule. Values only mentioning the domain as text are kept.
+    pub fn deny_value_hosts(mut self, hosts: &[&str]) -> Self {
+        for host in hosts {
+            let host = host.trim_start_matches('.');
+            let host = idna::domain_to_ascii(host).unwrap_or_else(|_| host.to_ascii_lowercase());
+            if !self.deny_value_hosts.contains(&host) {
+                self.deny_value_hosts.push(host);
+            }
+        }
+        self
+    }
+
+    /// Split the query on `;` as well as `&`, like older sites and some Java frameworks emit
+    /// `?a=1;utm_source=x;b=2`. Encoded `%3B` in values are kept. When a parameter is removed,
+    /// the query is written back with `&` only; otherwise the URL is left as is.
+    pub fn accept_semicolon_separator(mut self, enable: bool) -> Self {
+        self.semicolon_separator = enable;
+        self
+    }
+
+    /// Same as [`UrlCleanerBuilder::accept_semicolon_separator`], but always write the query
+    /// back with `&`, even if no parameter is removed.
+    pub fn rewrite_semicolon_separator(mut self, enable: bool) -> Self {
+        self.semicolon_separator = enable;
+        self.rewrite_semicolon_separator = enable;
+        self
+    }
+
+    /// List the parameters kept by the rules that look like tracking in the reports of
+    /// [`UrlCleaner::explain`], like UUIDs, long encoded blobs or keys ending with `clid`.
+    /// They are only reported, never removed, to help improving the ruleset.
+    pub fn flag_suspicious_params(mut self, enable: bool) -> Self {
+        self.flag_suspicious = enable;
+        self
+    }
+
+    /// Choose whether the values of the removed parameters are reported, in
+    /// [`ClearReport::removed_values`](crate::ClearReport::removed_values) and the
+    /// [`CleanEvent`]s. Only the keys are reported by default.
+    pub fn report_detail(mut self, detail: ReportDetail) -> Self {
+        self.report_detail = detail;
+        self
+    }
+
+    /// Allow processing URLs with the given scheme, in addition to `http` and `https`.
+    pub fn allow_scheme(mut self, scheme: &str) -> Self {
+        let scheme = scheme.to_ascii_lowercase();
+        if !self.allowed_schemes.contains(&scheme) {
+            self.allowed_schemes.push(scheme);
+        }
+        self
+    }
+
+    /// Accept Android `intent://` links, like the ones of share sheets, and clean the web URL
+    /// they wrap: the embedded link under the scheme of the `#Intent` block, or else its
+    /// `S.browser_fallback_url`. Disabled by default, where they are an unsupported scheme.
+    pub fn intent_links(mut self, enable: bool) -> Self {
+        self.intent_links = enable;
+        self
+    }
+
+    /// Choose what happens when cleaning leaves only the origin of a URL that had a path or
+    /// a query, for rules that don't set `if_reduced_to_origin`. The origin is returned by
+    /// default.
+    pub fn if_reduced_to_origin(mut self, policy: OriginPolicy) -> Self {
+        self.reduced_to_origin = policy;
+        self
+    }
+
+    #[cfg(feature = "http")]
+    /// Choose what happens to the userinfo of a URL before following its redirection. It is
+    /// stripped by default.
+    pub fn redirect_credentials(mut self, policy: RedirectCredentials) -> Self {
+        self.redirect_credentials = policy;
+        self
+    }
+
+    #[cfg(feature = "http")]
+    /// Remember the `Retry-After` cooldown of at most this many hosts, 1024 by default. When
+    /// full, the host whose cooldown ends first is forgotten.
+    pub fn cooldown_capacity(mut self, capacity: usize) -> Self {
+        self.cooldown_capacity = capacity;
+        self
+    }
+
+    #[cfg(feature = "http")]
+    /// Resolve shorteners with a GET request even when HEAD succeeds, and flag the ones
+    /// sending the two to different targets in
+    /// [`ClearReport::redirect_chain`](crate::ClearReport::redirect_chain). The target of
+    /// the GET request, the one browsers see, is followed. Disabled by default, it doubles
+    /// the requests.
+    pub fn detect_cloaking(mut self, enable: bool) -> Self {
+        self.detect_cloaking = enable;
+        self
+    }
+
+    #[cfg(feature = "http")]
+    /// Resolve at most this many URLs at once when cleaning a text with
+    /// [`UrlCleaner::clear_text`], 8 by default. A concurrency of 0 is taken as 1.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Install an observer receiving [`CleanEvent`]s. It is called synchronously from within
+    /// `clear()`, so it should return quickly.
+    pub fn on_event(mut self, observer: impl Fn(CleanEvent) + Send + Sync + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    #[cfg(feature = "hooks-core")]
+    /// Skip the given hook even if rules reference it.
+    pub fn disable_hook(mut self, name: &str) -> Self {
+        self.disabled_hooks.push(name.to_string());
+        self
+    }
+
+    /// Build the cleaner.
+    ///
+    /// # Error
+    ///
+    /// Return error when fail to read the rules file, the rules meet unexpected format, or
+    /// have warnings in strict mode. Remote rules are only fetched by
+    /// [`UrlCleanerBuilder::build_async`], they are an error here.
+    pub fn build(self) -> Result<UrlCleaner, RuleParseError> {
+        #[cfg(feature = "remote_rules")]
+        if let Some((url, _)) = &self.remote {
+            return Err(RuleParseError::Fetch {
+                url: url.to_string(),
+                message: "remote rules are only fetched by `build_async`".to_string(),
+            });
+        }
+
+        let (rules, warnings) = match &self.rules {
+            Some(RuleSource::File(path)) => {
+                let content = rules::read_file(path)?;
+                rules::parse_with(&content, Some(path), &self.parse_options)?
+            }
+            Some(RuleSource::Toml(content)) => {
+                rules::parse_with(content, None, &self.parse_options)?
+            }
+            Some(RuleSource::Presets(presets)) => {
+                rules::parse_documents(&preset::documents(presets), &self.parse_options)?
+            }
+            None => Default::default(),
+        };
+        self.finish(rules, warnings)
+    }
+
+    #[cfg(feature = "remote_rules")]
+    /// Same as [`UrlCleanerBuilder::build`], but fetch the remote rules first if set with
+    /// [`UrlCleanerBuilder::remote_rules`], and start refreshing them in the background.
+    ///
+    /// # Error
+    ///
+    /// Return the same errors as [`UrlCleanerBuilder::build`], and
+    /// [`RuleParseError::Fetch`] or [`RuleParseError::Sha256Mismatch`] when neither the
+    /// remote ruleset nor its cached copy is usable.
+    pub async fn build_async(mut self) -> Result<UrlCleaner, RuleParseError> {
+        let Some((url, policy)) = self.remote.take() else {
+            return self.build();
+        };
+        let remote = Remote {
+            url,
+            policy,
+            client: self.http_client.clone().unwrap_or_default(),
+            local: self.local_documents()?,
+            parse_options: self.parse_options.clone(),
+            observer: self.observer.clone(),
+        };
+
+        let (rules, warnings, etag) = remote.load().await?;
+        let cleaner = self.finish(rules, warnings)?;
+        remote.spawn_refresh(Arc::downgrade(&cleaner.rules), etag);
+        Ok(cleaner)
+    }
+
+    #[cfg(feature = "remote_rules")]
+    /// The documents of the local ruleset, in merge order.
+    fn local_documents(&self) -> Result<Vec<(String, Option<PathBuf>)>, RuleParseError> {
+        Ok(match &self.rules {
+            Some(RuleSource::File(path)) => vec![(rules::read_file(path)?, Some(path.clone()))],
+            Some(RuleSource::Toml(content)) => vec![(content.clone(), None)],
+            Some(RuleSource::Presets(presets)) => preset::documents(presets)
+                .into_iter()
+                .map(|(content, file)| (content.to_string(), file.map(Path::to_path_buf)))
+                .collect(),
+            None => Vec::new(),
+        })
+    }
+
+    fn finish(
+        self,
+        rules: rules::Rules,
+        warnings: Vec<RuleWarning>,
+    ) -> Result<UrlCleaner, RuleParseError> {
+        if self.strict && !warnings.is_empty() {
+            return Err(RuleParseError::Strict(warnings));
+        }
+
+        Ok(UrlCleaner {
+            rules: Arc::new(ArcSwap::from_pointee(rules)),
+            // default with HTTP/s proxy and 10 max redirect hop policy
+            // a client given by the user is trusted to follow redirections or not as needed
+            #[cfg(all(feature = "hooks-core", feature = "http"))]
+            hook_client: self.http_client.clone().unwrap_or_else(|| {
+                reqwest::Client::builder()
+                    .redirect(reqwest::redirect::Policy::none())
+                    .build()
+                    .unwrap_or_default()
+            }),
+            #[cfg(feature = "http")]
+            http_client: self.http_client.unwrap_or_default(),
+            options: Arc::new(Options {
+                normalization: self.normalization,
+                limits: self.limits,
+                deny_value_hosts: self.deny_value_hosts,
+                semicolon_separator: self.semicolon_separator,
+                rewrite_semicolon_separator: self.rewrite_semicolon_separator,
+                flag_suspicious: self.flag_suspicious,
+                report_detail: self.report_detail,
+                reduced_to_origin: self.reduced_to_origin,
+                allowed_schemes: self.allowed_schemes,
+                intent_links: self.intent_links,
+                #[cfg(feature = "http")]
+                redirect_credentials: self.redirect_credentials,
+                #[cfg(feature = "http")]
+                detect_cloaking: self.detect_cloaking,
+                #[cfg(feature = "http")]
+                concurrency: self.concurrency,
+                #[cfg(feature = "http")]
+                cooldowns: Arc::new(cooldown::Cooldowns::new(self.cooldown_capacity)),
+                observer: self.observer,
+                #[cfg(feature = "hooks-core")]
+                disabled_hooks: self.disabled_hooks,
+                warnings,
+            }),
+        })
+    }
+}
@@ -0,0 +1,128 @@
+//! A URL type that can only come out of the cleaner, so dirty URLs can't be mistaken for
+//! cleaned ones by the type system.
+
+use std::fmt;
+use std::ops::Deref;
+
+use serde::de::{DeserializeSeed, Error as _};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use url::Url;
+
+use crate::{ClearOutcome, UrlCleaner};
+
+/// A URL returned by the cleaner, see [`ClearOutcome::into_cleaned`]. It dereferences to
+/// [`Url`] and is serialized as a plain string.
+///
+/// Deserializing trusts the stored string. Use [`CleanedUrl::deserialize_with`] to check it
+/// against a cleaner instead.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CleanedUrl(pub(crate) Url);
+
+impl CleanedUrl {
+    /// The wrapped URL.
+    pub fn into_inner(self) -> Url {
+        self.0
+    }
+
+    /// A [`DeserializeSeed`] that fails unless the URL is already clean by `cleaner`, only
+    /// offline rules and hooks are checked.
+    pub fn deserialize_with(cleaner: &UrlCleaner) -> CleanedUrlSeed<'_> {
+        CleanedUrlSeed { cleaner }
+    }
+}
+
+impl Deref for CleanedUrl {
+    type Target = Url;
+
+    fn deref(&self) -> &Url {
+        &self.0
+    }
+}
+
+impl AsRef<Url> for CleanedUrl {
+    fn as_ref(&self) -> &Url {
+        &self.0
+    }
+}
+
+impl AsRef<str> for CleanedUrl {
+    fn as_ref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl From<CleanedUrl> for Url {
+    fn from(url: CleanedUrl) -> Self {
+        url.0
+    }
+}
+
+impl fmt::Display for CleanedUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Serialize for CleanedUrl {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CleanedUrl {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let url = String::deserialize(deserializer)?;
+        Url::parse(&url).map(Self).map_err(D::Error::custom)
+    }
+}
+
+/// Deserialize a [`CleanedUrl`] checked against a cleaner, made by
+/// [`CleanedUrl::deserialize_with`].
+pub struct CleanedUrlSeed<'a> {
+    cleaner: &'a UrlCleaner,
+}
+
+impl<'de> DeserializeSeed<'de> for CleanedUrlSeed<'_> {
+    type Value = CleanedUrl;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<CleanedUrl, D::Error> {
+        let url = String::deserialize(deserializer)?;
+        match self
+            .cleaner
+            .try_clear_offline(&url)
+            .map_err(D::Error::custom)?
+        {
+            ClearOutcome::AlreadyClean(url) => Ok(CleanedUrl(url)),
+            ClearOutcome::Cleaned(_) => Err(D::Error::custom(format!("URL {url} is not clean"))),
+        }
+    }
+}
+
+#[test]
+fn test_serde_round_trip() {
+    let cleaner = UrlCleaner::from_toml("[\"example.com\"]\nban = [\"utm_source\"]\n").unwrap();
+    let cleaned = cleaner
+        .try_clear_offline("https://example.com/?id=1&utm_source=a")
+        .unwrap()
+        .into_cleaned();
+    // dereferences to the URL
+    assert_eq!(cleaned.query(), Some("id=1"));
+    assert_eq!(cleaned.to_string(), "https://example.com/?id=1");
+
+    let value = toml::Value::try_from(&cleaned).unwrap();
+    assert_eq!(value.as_str(), Some("https://example.com/?id=1"));
+    assert_eq!(CleanedUrl::deserialize(value.clone()).unwrap(), cleaned);
+    assert_eq!(
+        CleanedUrl::deserialize_with(&cleaner)
+            .deserialize(value)
+            .unwrap(),
+        cleaned
+    );
+
+    let dirty = toml::Value::from("https://example.com/?utm_source=a");
+    assert!(CleanedUrl::deserialize(dirty.clone()).is_ok());
+    assert!(CleanedUrl::deserialize_with(&cleaner)
+        .deserialize(dirty)
+        .is_err());
+    assert_eq!(cleaned.into_inner().as_str(), "https://example.com/?id=1");
+}
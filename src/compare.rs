@@ -0,0 +1,199 @@
+//! Dry run of a ruleset change: clean a corpus of URLs with the old and the new rules, and
+//! tell what would change.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::report::ClearReport;
+use crate::UrlCleaner;
+
+/// How many changed URLs [`compare_rulesets`] keeps as samples for each domain.
+pub const SAMPLES_PER_DOMAIN: usize = 3;
+
+/// What a ruleset change does to a corpus of URLs, returned by [`compare_rulesets`].
+///
+/// Outputs are the cleaned URL, the URL as is when there is nothing to clean, or the error
+/// message. Displayed as a short summary, and serialized for machine consumption.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CorpusDiff {
+    /// Number of URLs compared.
+    pub urls: usize,
+    /// Number of URLs whose output changed.
+    pub changed: usize,
+    /// Changed URLs by host, at most [`SAMPLES_PER_DOMAIN`] each. URLs that don't parse are
+    /// under the empty host.
+    pub samples: BTreeMap<String, Vec<OutputChange>>,
+    /// Keys removed by the new rules but not the old ones, with the number of URLs.
+    pub newly_removed: BTreeMap<String, usize>,
+    /// Keys removed by the old rules but no longer by the new ones, with the number of URLs.
+    pub no_longer_removed: BTreeMap<String, usize>,
+    /// Number of URLs that fail with the new rules but not with the old ones.
+    pub errors_introduced: usize,
+}
+
+/// The outputs of the old and new rules for one URL.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct OutputChange {
+    pub input: String,
+    pub before: Result<String, String>,
+    pub after: Result<String, String>,
+}
+
+/// Clean every URL with both cleaners, without network access, and aggregate the
+/// differences. Redirections are not followed, short links fail with
+/// [`UrlCleanError::RedirectRequired`](crate::UrlCleanError::RedirectRequired) on both
+/// sides.
+pub fn compare_rulesets(
+    old: &UrlCleaner,
+    new: &UrlCleaner,
+    urls: impl IntoIterator<Item = String>,
+) -> CorpusDiff {
+    let mut diff = CorpusDiff::default();
+    for input in urls {
+        diff.urls += 1;
+        let (before, removed_before) = run(old, &input);
+        let (after, removed_after) = run(new, &input);
+
+        for key in removed_after.difference(&removed_before) {
+            *diff.newly_removed.entry(key.clone()).or_default() += 1;
+        }
+        for key in removed_before.difference(&removed_after) {
+            *diff.no_longer_removed.entry(key.clone()).or_default() += 1;
+        }
+        if before == after {
+            continue;
+        }
+        diff.changed += 1;
+        if after.is_err() && before.is_ok() {
+            diff.errors_introduced += 1;
+        }
+        let host = url::Url::parse(&input)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let samples = diff.samples.entry(host).or_default();
+        if samples.len() < SAMPLES_PER_DOMAIN {
+            samples.push(OutputChange {
+                input,
+                before,
+                after,
+            });
+        }
+    }
+    diff
+}
+
+/// The output of the offline cleaning and the keys it removed.
+fn run(cleaner: &UrlCleaner, input: &str) -> (Result<String, String>, BTreeSet<String>) {
+    let mut report = ClearReport::default();
+    let applied = cleaner.clear_offline_traced(input, &mut cleaner.trace(Some(&mut report)));
+    let output = applied
+        .map(|applied| applied.into_outcome().into_url().to_string())
+        .map_err(|err| err.to_string());
+    (output, report.removed_params.into_iter().collect())
+}
+
+impl fmt::Display for CorpusDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} URLs, {} changed, {} new errors",
+            self.urls, self.changed, self.errors_introduced
+        )?;
+        for (key, count) in &self.newly_removed {
+            writeln!(f, "+ {key} removed from {count} URLs")?;
+        }
+        for (key, count) in &self.no_longer_removed {
+            writeln!(f, "- {key} kept in {count} URLs")?;
+        }
+        let show = |output: &Result<String, String>| match output {
+            Ok(url) => url.clone(),
+            Err(err) => format!("error: {err}"),
+        };
+        for (host, samples) in &self.samples {
+            writeln!(f, "{host}:")?;
+            for sample in samples {
+                writeln!(f, "  {}", sample.input)?;
+                writeln!(f, "    before: {}", show(&sample.before))?;
+                writeln!(f, "    after:  {}", show(&sample.after))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_compare_rulesets() {
+    let old = UrlCleaner::from_toml(
+        r#"
+["example.com"]
+ban = ["utm_source", "ref"]
+
+["shop.test"]
+ban = ["spm"]
+"#,
+    )
+    .unwrap();
+    // `ref` is no longer removed on example.com, `fbclid` now is
+    let new = UrlCleaner::from_toml(
+        r#"
+["example.com"]
+ban = ["utm_source", "fbclid"]
+
+["shop.test"]
+ban = ["spm"]
+"#,
+    )
+    .unwrap();
+
+    let mut corpus = Vec::new();
+    for i in 0..5 {
+        corpus.push(format!("https://example.com/{i}?fbclid=x"));
+        corpus.push(format!("https://example.com/{i}?ref=y&id={i}"));
+        corpus.push(format!("https://example.com/{i}?utm_source=z"));
+        corpus.push(format!("https://shop.test/{i}?spm=a&fbclid=x"));
+    }
+    assert_eq!(corpus.len(), 20);
+
+    let diff = compare_rulesets(&old, &new, corpus);
+    assert_eq!(diff.urls, 20);
+    assert_eq!(diff.changed, 10);
+    assert_eq!(diff.errors_introduced, 0);
+    assert_eq!(diff.newly_removed, BTreeMap::from([("fbclid".into(), 5)]));
+    assert_eq!(diff.no_longer_removed, BTreeMap::from([("ref".into(), 5)]));
+    assert_eq!(diff.samples.len(), 1);
+    assert_eq!(diff.samples["example.com"].len(), SAMPLES_PER_DOMAIN);
+    assert_eq!(
+        diff.samples["example.com"][0],
+        OutputChange {
+            input: "https://example.com/0?fbclid=x".into(),
+            before: Ok("https://example.com/0?fbclid=x".into()),
+            after: Ok("https://example.com/0".into()),
+        }
+    );
+    assert!(diff
+        .to_string()
+        .starts_with("20 URLs, 10 changed, 0 new errors\n+ fbclid removed from 5 URLs\n"));
+
+    assert_eq!(
+        compare_rulesets(&old, &old, Vec::new()),
+        CorpusDiff::default()
+    );
+
+    let strict = UrlCleaner::from_toml(
+        r#"
+["shop.test"]
+ban = ["spm"]
+if_reduced_to_origin = "error"
+"#,
+    )
+    .unwrap();
+    let diff = compare_rulesets(&old, &strict, ["https://shop.test/?spm=a".to_string()]);
+    assert_eq!((diff.changed, diff.errors_introduced), (1, 1));
+    assert_eq!(
+        diff.samples["shop.test"][0].after,
+        Err("cleaning would leave only the origin of the URL".into())
+    );
+}
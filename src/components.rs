@@ -0,0 +1,62 @@
+use std::ops::{BitOr, BitOrAssign};
+
+use url::{Position, Url};
+
+/// The stages of cleaning to run with
+/// [`UrlCleaner::clean_components`](crate::UrlCleaner::clean_components), combined with `|`.
+/// Stages not selected are skipped and can't affect the output.
+///
+/// Hooks and normalization apply only when every component they change is selected.
+/// Otherwise they are skipped as a whole, reported by `CleanEvent::HookSkipped` for hooks.
+/// Hooks that need network also require [`ComponentMask::REDIRECT`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ComponentMask(u8);
+
+impl ComponentMask {
+    /// Remove banned query parameters, and let hooks change the query.
+    pub const QUERY: Self = Self(1);
+    /// Drop the fragment, and let hooks change it.
+    pub const FRAGMENT: Self = Self(1 << 1);
+    /// Remove matrix parameters from path segments, and let hooks change the path.
+    pub const PATH_REWRITES: Self = Self(1 << 2);
+    /// Let hooks and normalization change the scheme, userinfo, host or port.
+    pub const HOST_REWRITES: Self = Self(1 << 3);
+    /// Follow redirection and run the hooks that need network.
+    pub const REDIRECT: Self = Self(1 << 4);
+    /// The full pipeline, as run by `UrlCleaner::clear`.
+    pub const ALL: Self = Self(0b1_1111);
+
+    /// Whether all the stages of `other` are selected.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether every component changed from `before` to `after` is selected.
+    pub(crate) fn allows(self, before: &Url, after: &Url) -> bool {
+        (self.contains(Self::HOST_REWRITES)
+            || before[..Position::BeforePath] == after[..Position::BeforePath])
+            && (self.contains(Self::PATH_REWRITES) || before.path() == after.path())
+            && (self.contains(Self::QUERY) || before.query() == after.query())
+            && (self.contains(Self::FRAGMENT) || before.fragment() == after.fragment())
+    }
+}
+
+impl Default for ComponentMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl BitOr for ComponentMask {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for ComponentMask {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
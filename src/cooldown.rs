@@ -0,0 +1,189 @@
+//! Hosts that asked us to slow down with `Retry-After`, so redirects to them are not
+//! attempted again before the deadline.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The longest cooldown honored, so a bogus `Retry-After` can't disable a host for good.
+const MAX_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+/// Cooldown deadline of each host, shared by all clones of a cleaner.
+#[derive(Debug)]
+pub(crate) struct Cooldowns {
+    hosts: Mutex<HashMap<String, Instant>>,
+    /// Most hosts remembered at once
+    capacity: usize,
+}
+
+impl Cooldowns {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            hosts: Default::default(),
+            capacity,
+        }
+    }
+
+    /// When the host may be contacted again, `None` if it isn't cooling down.
+    pub fn until(&self, host: &str) -> Option<Instant> {
+        let hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        hosts
+            .get(host)
+            .copied()
+            .filter(|&until| until > Instant::now())
+    }
+
+    /// Cool the host down for the given delay, dropping the deadlines that have passed.
+    pub fn record(&self, host: &str, delay: Duration) -> Instant {
+        let now = Instant::now();
+        let until = now + delay.min(MAX_COOLDOWN);
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        hosts.retain(|_, deadline| *deadline > now);
+        if self.capacity == 0 {
+            return until;
+        }
+        if hosts.len() >= self.capacity && !hosts.contains_key(host) {
+            let first = hosts
+                .iter()
+                .min_by_key(|(_, deadline)| **deadline)
+                .map(|(host, _)| host.clone());
+            if let Some(first) = first {
+                hosts.remove(&first);
+            }
+        }
+        hosts.insert(host.to_string(), until);
+        until
+    }
+
+    /// Number of hosts remembered and the approximate bytes they use on the heap.
+    pub fn usage(&self) -> (usize, usize) {
+        let hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = std::mem::size_of::<(String, Instant)>() + 1;
+        let names: usize = hosts.keys().map(String::capacity).sum();
+        (hosts.len(), hosts.capacity() * entry + names)
+    }
+
+    /// Forget every cooldown.
+    pub fn clear(&self) {
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        *hosts = HashMap::new();
+    }
+}
+
+/// The delay asked by a `Retry-After` header, given in seconds or as an HTTP date.
+pub(crate) fn retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = parse_http_date(value)?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+/// Parse the IMF-fixdate format of HTTP, like `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let (_, date) = value.split_once(", ")?;
+    let mut parts = date.split(' ');
+    let day: u64 = parts.next()?.parse().ok()?;
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|&m| m == month)? as u64 + 1;
+    let year: u64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|n| n.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if parts.next()? != "GMT" || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60
+    {
+        return None;
+    }
+    // IMF-fixdate years have four digits, larger ones only come from broken servers
+    if year > 9999 {
+        return None;
+    }
+
+    // days since the epoch of a proleptic Gregorian date
+    let (y, m) = match month <= 2 {
+        true => (year.checked_sub(1)?, month + 9),
+        false => (year, month - 3),
+    };
+    let era = y / 400;
+    let year_of_era = y % 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era
+        .checked_mul(146097)?
+        .checked_add(day_of_era)?
+        .checked_sub(719468)?;
+    let seconds = days
+        .checked_mul(86400)?
+        .checked_add(hour * 3600 + minute * 60 + second)?;
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+#[test]
+fn test_retry_after() {
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(784111777);
+    assert_eq!(retry_after("120", now), Some(Duration::from_secs(120)));
+    assert_eq!(
+        retry_after("Sun, 06 Nov 1994 08:49:37 GMT", now),
+        Some(Duration::ZERO)
+    );
+    assert_eq!(
+        retry_after("Sun, 06 Nov 1994 08:51:37 GMT", now),
+        Some(Duration::from_secs(120))
+    );
+    assert_eq!(
+        retry_after("Thu, 01 Jan 1970 00:00:00 GMT", SystemTime::UNIX_EPOCH),
+        Some(Duration::ZERO)
+    );
+    assert_eq!(retry_after("soon", now), None);
+    assert_eq!(retry_after("Sun, 06 Foo 1994 08:49:37 GMT", now), None);
+    // a huge year is rejected instead of overflowing
+    assert_eq!(
+        retry_after("Sun, 06 Nov 1000000000000 08:49:37 GMT", now),
+        None
+    );
+    assert_eq!(
+        retry_after("Sun, 06 Nov 1994 08:49:18446744073709551615 GMT", now),
+        None
+    );
+    assert_eq!(
+        retry_after("Fri, 31 Dec 9999 23:59:59 GMT", now).map(|d| d > Duration::ZERO),
+        Some(true)
+    );
+
+    let cooldowns = Cooldowns::new(16);
+    assert!(cooldowns.until("t.co").is_none());
+    let until = cooldowns.record("t.co", Duration::from_secs(60 * 60 * 24));
+    assert!(until <= Instant::now() + MAX_COOLDOWN);
+    assert_eq!(cooldowns.until("t.co"), Some(until));
+    cooldowns.record("b23.tv", Duration::ZERO);
+    assert!(cooldowns.until("b23.tv").is_none());
+}
+
+#[test]
+fn test_cooldown_capacity() {
+    let cooldowns = Cooldowns::new(2);
+    cooldowns.record("a.test", Duration::from_secs(10));
+    cooldowns.record("b.test", Duration::from_secs(30));
+    assert_eq!(cooldowns.usage().0, 2);
+    // the cooldown ending first makes room
+    cooldowns.record("c.test", Duration::from_secs(20));
+    assert_eq!(cooldowns.usage().0, 2);
+    assert!(cooldowns.until("a.test").is_none());
+    assert!(cooldowns.until("b.test").is_some());
+    assert!(cooldowns.until("c.test").is_some());
+    // a known host is updated in place
+    cooldowns.record("b.test", Duration::from_secs(5));
+    assert!(cooldowns.until("c.test").is_some());
+
+    let (_, bytes) = cooldowns.usage();
+    assert!(bytes >= 2 * std::mem::size_of::<(String, Instant)>());
+    cooldowns.clear();
+    assert_eq!(cooldowns.usage(), (0, 0));
+
+    let disabled = Cooldowns::new(0);
+    disabled.record("a.test", Duration::from_secs(10));
+    assert!(disabled.until("a.test").is_none());
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::RemovedValue;
+#[cfg(feature = "remote_rules")]
+use crate::RulesetDiff;
+
+/// Events emitted by [`UrlCleaner::clear()`](crate::UrlCleaner::clear) to the observer set by
+/// [`UrlCleanerBuilder::on_event`](crate::UrlCleanerBuilder::on_event). Query values are
+/// only included when asked for with
+/// [`UrlCleanerBuilder::report_detail`](crate::UrlCleanerBuilder::report_detail).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CleanEvent {
+    /// A query parameter is removed by the rule of `domain`.
+    ParamRemoved {
+        domain: String,
+        key: String,
+        value: Option<RemovedValue>,
+    },
+    /// A query parameter is removed because its value embeds a URL of the denied host
+    /// `denied`, as configured with
+    /// [`UrlCleanerBuilder::deny_value_hosts`](crate::UrlCleanerBuilder::deny_value_hosts).
+    ParamValueDenied {
+        domain: String,
+        key: String,
+        value: Option<RemovedValue>,
+        denied: String,
+    },
+    /// A redirect is followed, taking `elapsed` to resolve. `hops` counts the shorteners
+    /// resolved in a row, when the target of one is another shortener.
+    RedirectFollowed {
+        from_host: String,
+        to_host: String,
+        hops: usize,
+        elapsed: Duration,
+    },
+    /// A post hook is applied.
+    HookApplied { name: String },
+    /// A post hook is skipped, because it would change a component not selected by the
+    /// [`ComponentMask`](crate::ComponentMask).
+    HookSkipped { name: String },
+    /// No rule is defined for `domain`, the default rule is used if any.
+    RuleMissed { domain: String },
+    /// The remote ruleset changed and the rules are replaced, see
+    /// [`UrlCleanerBuilder::remote_rules`](crate::UrlCleanerBuilder::remote_rules).
+    #[cfg(feature = "remote_rules")]
+    RulesRefreshed { diff: RulesetDiff },
+    /// Refreshing the remote ruleset failed, the current rules are kept.
+    #[cfg(feature = "remote_rules")]
+    RulesRefreshFailed { message: String },
+}
+
+pub(crate) type Observer = Arc<dyn Fn(CleanEvent) + Send + Sync>;
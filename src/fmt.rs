@@ -0,0 +1,163 @@
+//! Render cleaned URLs and reports for messages sent with a markup parse mode, like the
+//! MarkdownV2 and HTML modes of Telegram, where characters of the URL would otherwise be
+//! taken as formatting.
+
+use url::Url;
+
+use crate::ClearReport;
+
+/// Characters that must be escaped anywhere in MarkdownV2 text.
+const MARKDOWN_V2_SPECIAL: &[char] = &[
+    '\\', '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!',
+];
+
+/// Escape the URL to be sent as plain MarkdownV2 text.
+pub fn markdown_v2_escape(url: &Url) -> String {
+    escape_markdown_v2(url.as_str())
+}
+
+/// Escape the URL to be sent as HTML text or attribute value.
+pub fn html_escape(url: &Url) -> String {
+    let mut escaped = String::with_capacity(url.as_str().len());
+    for c in url.as_str().chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Describe the report as a MarkdownV2 block, one line per step that happened, with the
+/// removed parameters and applied hooks in inline code.
+pub fn format_report_markdown(report: &ClearReport) -> String {
+    let mut lines = Vec::new();
+    if let Some(rule) = &report.matched_rule {
+        lines.push(format!("*Rule:* {}", code(rule)));
+    }
+    if let Some(url) = &report.redirected_to {
+        lines.push(format!("*Redirected to:* {}", markdown_v2_escape(url)));
+    }
+    if report.params_removed() {
+        lines.push(format!("*Removed:* {}", code_list(&report.removed_params)));
+    }
+    if !report.added_params.is_empty() {
+        lines.push(format!("*Added:* {}", code_list(&report.added_params)));
+    }
+    if !report.hooks_applied.is_empty() {
+        lines.push(format!("*Hooks:* {}", code_list(&report.hooks_applied)));
+    }
+    if report.params_truncated {
+        lines.push(format!(
+            "_{}_",
+            escape_markdown_v2("Too many parameters, only the first ones are examined.")
+        ));
+    }
+    lines.join("\n")
+}
+
+/// List the keys of the removed parameters in MarkdownV2 inline code, at most `max` of
+/// them followed by `+N more`. `None` if nothing is removed. Values are never included.
+pub fn format_removed_params(report: &ClearReport, max: usize) -> Option<String> {
+    let removed = &report.removed_params;
+    if removed.is_empty() {
+        return None;
+    }
+    let shown = code_list(&removed[..removed.len().min(max)]);
+    Some(match removed.len().saturating_sub(max) {
+        0 => shown,
+        more if shown.is_empty() => escape_markdown_v2(&format!("+{more} more")),
+        more => format!("{shown} {}", escape_markdown_v2(&format!("+{more} more"))),
+    })
+}
+
+fn escape_markdown_v2(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if MARKDOWN_V2_SPECIAL.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Inline code, where only the backtick and backslash need escaping.
+fn code(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('`');
+    for c in text.chars() {
+        if matches!(c, '`' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.push('`');
+    escaped
+}
+
+fn code_list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| code(item))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[test]
+fn test_markdown_v2_escape() {
+    let url =
+        Url::parse("https://example.com/a_b*c[d](e)~f`g>h#i+j-k=l|m{n}o.p!q?x=1&y=2").unwrap();
+    assert_eq!(
+        markdown_v2_escape(&url),
+        r"https://example\.com/a\_b\*c\[d\]\(e\)\~f%60g%3Eh\#i\+j\-k\=l\|m\{n\}o\.p\!q?x\=1&y\=2"
+    );
+
+    let report = ClearReport {
+        matched_rule: Some("example.com".to_string()),
+        redirected_to: Some(Url::parse("https://example.com/a_b").unwrap()),
+        removed_params: vec!["utm_source".to_string(), "we`ird".to_string()],
+        hooks_applied: vec!["fixup_twitter".to_string()],
+        params_truncated: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        format_report_markdown(&report),
+        "*Rule:* `example.com`\n\
+         *Redirected to:* https://example\\.com/a\\_b\n\
+         *Removed:* `utm_source`, `we\\`ird`\n\
+         *Hooks:* `fixup_twitter`\n\
+         _Too many parameters, only the first ones are examined\\._"
+    );
+    assert_eq!(format_report_markdown(&ClearReport::default()), "");
+}
+
+#[test]
+fn test_html_escape() {
+    let url = Url::parse("https://example.com/?a=1&b=<2>&c=\"3\"&d=&amp;").unwrap();
+    assert_eq!(
+        html_escape(&url),
+        "https://example.com/?a=1&amp;b=%3C2%3E&amp;c=%223%22&amp;d=&amp;amp;"
+    );
+}
+
+#[test]
+fn test_format_removed_params() {
+    let report = ClearReport {
+        removed_params: (1..=10).map(|i| format!("p_{i}")).collect(),
+        ..Default::default()
+    };
+    assert_eq!(
+        format_removed_params(&report, 8).unwrap(),
+        "`p_1`, `p_2`, `p_3`, `p_4`, `p_5`, `p_6`, `p_7`, `p_8` \\+2 more"
+    );
+    assert_eq!(
+        format_removed_params(&report, 10).unwrap(),
+        "`p_1`, `p_2`, `p_3`, `p_4`, `p_5`, `p_6`, `p_7`, `p_8`, `p_9`, `p_10`"
+    );
+    assert_eq!(format_removed_params(&report, 0).unwrap(), "\\+10 more");
+    assert_eq!(format_removed_params(&ClearReport::default(), 8), None);
+}
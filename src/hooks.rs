@@ -1,17 +1,310 @@
+//! Hooks rewriting URLs beyond query filtering, referenced by name from the ruleset.
+
 use std::collections::HashMap;
 
 use lazy_static::lazy_static;
 
-type HookFn = fn(input: &url::Url) -> anyhow::Result<url::Url>;
+#[cfg_attr(not(feature = "hooks"), allow(unused_imports))]
+use crate::text;
+
+/// Arguments given to a hook in the ruleset.
+pub type HookArgs = HashMap<String, String>;
+
+type HookFn = fn(input: &url::Url, args: &HookArgs) -> anyhow::Result<url::Url>;
+
+/// Rewrite hooks that decide whether the hooks listed after them still run.
+type ChainedHookFn = fn(input: &url::Url, args: &HookArgs) -> anyhow::Result<HookOutcome>;
+
+/// The URL a rewrite hook hands over to the rest of the chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum HookOutcome {
+    /// Run the next hooks of the rule on the URL.
+    Continue(url::Url),
+    /// The URL is final, the remaining hooks of the rule are skipped.
+    #[cfg_attr(not(any(test, feature = "hook-twitter")), allow(dead_code))]
+    Final(url::Url),
+}
+
+#[cfg(feature = "http")]
+type HookFuture<'a> =
+    std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<url::Url>> + Send + 'a>>;
+
+/// Hooks that need network. They run right after redirection, before the query is filtered,
+/// since they usually resolve the URL into another one. Offline cleaning skip them. Requests
+/// go through the cleaner, so hosts cooling down are not contacted.
+#[cfg(feature = "http")]
+type AsyncHookFn = for<'a> fn(
+    input: &'a url::Url,
+    args: &'a HookArgs,
+    cleaner: &'a crate::UrlCleaner,
+) -> HookFuture<'a>;
+
+/// Arguments a hook accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HookSpec {
+    pub required_args: &'static [&'static str],
+    pub optional_args: &'static [&'static str],
+}
+
+impl HookSpec {
+    const NO_ARGS: Self = Self {
+        required_args: &[],
+        optional_args: &[],
+    };
+    const OPTIONAL_HOST: Self = Self {
+        required_args: &[],
+        optional_args: &["host"],
+    };
+}
+
+/// Description of a hook, as listed by [`available`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HookInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The hook sends requests, so it only runs when cleaning online.
+    pub needs_network: bool,
+    pub args: HookSpec,
+}
+
+impl HookInfo {
+    const fn new(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            description,
+            needs_network: false,
+            args: HookSpec::NO_ARGS,
+        }
+    }
+
+    const fn with_args(mut self, args: HookSpec) -> Self {
+        self.args = args;
+        self
+    }
+
+    const fn network(mut self) -> Self {
+        self.needs_network = true;
+        self
+    }
+}
+
+/// How the cleaner runs a hook. Builds with only some of the hooks leave some kinds unused.
+#[derive(Clone, Copy)]
+#[cfg_attr(not(feature = "hooks"), allow(dead_code))]
+enum Runner {
+    /// Rewrite the URL after the query is filtered.
+    Rewrite(HookFn),
+    /// Rewrite the URL after the query is filtered, possibly ending the chain.
+    Chained(ChainedHookFn),
+    /// Extract the real target embedded in a wrapper URL. It runs before the query filter,
+    /// and the target is cleaned again by its own rule.
+    Unwrap(HookFn),
+    #[cfg(feature = "http")]
+    Network(AsyncHookFn),
+    /// Known, but not compiled in this build without `feature`.
+    #[allow(dead_code)]
+    Disabled { feature: &'static str },
+}
+
+struct Hook {
+    info: HookInfo,
+    runner: Runner,
+}
 
 lazy_static! {
-    pub static ref POST_HOOKS: HashMap<String, HookFn> = HashMap::from([
-            #[cfg(feature = "bilibili_hooks")]
-            ("bv_to_av".to_string(), bv_to_av as HookFn),
-            ("fixup_twitter".to_string(), fixup_twitter as HookFn),
-            ("fixup_zhihu".to_string(), fixup_zhihu as HookFn)
-        ]);
+    /// Every hook known to this crate, including the ones disabled by feature flags, so
+    /// rulesets stay valid across builds. Each hook is compiled in by its `hook-*` feature.
+    static ref REGISTRY: Vec<Hook> = vec![
+        #[cfg(feature = "hook-bilibili")]
+        Hook { info: BV_TO_AV, runner: Runner::Rewrite(bv_to_av) },
+        #[cfg(not(feature = "hook-bilibili"))]
+        Hook { info: BV_TO_AV, runner: Runner::Disabled { feature: "hook-bilibili" } },
+        #[cfg(feature = "hook-bilibili")]
+        Hook { info: BILIBILI_MOBILE_TO_DESKTOP, runner: Runner::Rewrite(bilibili_mobile_to_desktop) },
+        #[cfg(not(feature = "hook-bilibili"))]
+        Hook { info: BILIBILI_MOBILE_TO_DESKTOP, runner: Runner::Disabled { feature: "hook-bilibili" } },
+        #[cfg(feature = "hook-twitter")]
+        Hook { info: FIXUP_TWITTER, runner: Runner::Chained(fixup_twitter) },
+        #[cfg(not(feature = "hook-twitter"))]
+        Hook { info: FIXUP_TWITTER, runner: Runner::Disabled { feature: "hook-twitter" } },
+        #[cfg(feature = "hook-zhihu")]
+        Hook { info: FIXUP_ZHIHU, runner: Runner::Rewrite(fixup_zhihu) },
+        #[cfg(not(feature = "hook-zhihu"))]
+        Hook { info: FIXUP_ZHIHU, runner: Runner::Disabled { feature: "hook-zhihu" } },
+        #[cfg(feature = "hook-spotify")]
+        Hook { info: SPOTIFY_CANONICAL, runner: Runner::Rewrite(spotify_canonical) },
+        #[cfg(not(feature = "hook-spotify"))]
+        Hook { info: SPOTIFY_CANONICAL, runner: Runner::Disabled { feature: "hook-spotify" } },
+        #[cfg(feature = "hook-tiktok")]
+        Hook { info: TIKTOK_CANONICAL, runner: Runner::Rewrite(tiktok_canonical) },
+        #[cfg(not(feature = "hook-tiktok"))]
+        Hook { info: TIKTOK_CANONICAL, runner: Runner::Disabled { feature: "hook-tiktok" } },
+        #[cfg(feature = "hook-instagram")]
+        Hook { info: INSTAGRAM_CANONICAL, runner: Runner::Rewrite(instagram_canonical) },
+        #[cfg(not(feature = "hook-instagram"))]
+        Hook { info: INSTAGRAM_CANONICAL, runner: Runner::Disabled { feature: "hook-instagram" } },
+        #[cfg(feature = "hook-facebook")]
+        Hook { info: FACEBOOK_CANONICAL, runner: Runner::Rewrite(facebook_canonical) },
+        #[cfg(not(feature = "hook-facebook"))]
+        Hook { info: FACEBOOK_CANONICAL, runner: Runner::Disabled { feature: "hook-facebook" } },
+        #[cfg(feature = "hook-steam")]
+        Hook { info: UNWRAP_STEAM_LINKFILTER, runner: Runner::Unwrap(unwrap_steam_linkfilter) },
+        #[cfg(not(feature = "hook-steam"))]
+        Hook { info: UNWRAP_STEAM_LINKFILTER, runner: Runner::Disabled { feature: "hook-steam" } },
+        #[cfg(feature = "hook-youtube")]
+        Hook { info: YOUTUBE_UNWRAP, runner: Runner::Unwrap(youtube_unwrap) },
+        #[cfg(not(feature = "hook-youtube"))]
+        Hook { info: YOUTUBE_UNWRAP, runner: Runner::Disabled { feature: "hook-youtube" } },
+        #[cfg(feature = "hook-ebay")]
+        Hook { info: EBAY_CANONICAL, runner: Runner::Rewrite(ebay_canonical) },
+        #[cfg(not(feature = "hook-ebay"))]
+        Hook { info: EBAY_CANONICAL, runner: Runner::Disabled { feature: "hook-ebay" } },
+        #[cfg(feature = "hook-aliexpress")]
+        Hook { info: ALIEXPRESS_SHARE_UNWRAP, runner: Runner::Unwrap(aliexpress_share_unwrap) },
+        #[cfg(not(feature = "hook-aliexpress"))]
+        Hook { info: ALIEXPRESS_SHARE_UNWRAP, runner: Runner::Disabled { feature: "hook-aliexpress" } },
+        #[cfg(feature = "hook-taobao")]
+        Hook { info: TAOBAO_CANONICAL, runner: Runner::Rewrite(taobao_canonical) },
+        #[cfg(not(feature = "hook-taobao"))]
+        Hook { info: TAOBAO_CANONICAL, runner: Runner::Disabled { feature: "hook-taobao" } },
+        #[cfg(all(feature = "hook-reddit", feature = "http"))]
+        Hook { info: REDDIT_SHARE, runner: Runner::Network(reddit_share) },
+        #[cfg(not(feature = "hook-reddit"))]
+        Hook { info: REDDIT_SHARE, runner: Runner::Disabled { feature: "hook-reddit" } },
+        #[cfg(all(feature = "hook-reddit", not(feature = "http")))]
+        Hook { info: REDDIT_SHARE, runner: Runner::Disabled { feature: "http" } },
+        #[cfg(test)]
+        Hook { info: TEST_TO_JAVASCRIPT, runner: Runner::Rewrite(test_to_javascript) },
+        #[cfg(test)]
+        Hook { info: TEST_SET_PATH, runner: Runner::Rewrite(test_set_path) },
+        #[cfg(test)]
+        Hook { info: TEST_APPEND_PATH, runner: Runner::Rewrite(test_append_path) },
+        #[cfg(test)]
+        Hook { info: TEST_FINAL_PATH, runner: Runner::Chained(test_final_path) },
+        #[cfg(test)]
+        Hook { info: TEST_ADD_PARAM, runner: Runner::Rewrite(test_add_param) },
+    ];
+
+    static ref BY_NAME: HashMap<&'static str, &'static Hook> =
+        REGISTRY.iter().map(|hook| (hook.info.name, hook)).collect();
+}
+
+/// List the hooks usable in this build, in registration order. Hooks disabled by feature
+/// flags are left out, rulesets referencing them still parse with a
+/// [`RuleWarning::HookNotCompiled`](crate::RuleWarning::HookNotCompiled).
+pub fn available() -> Vec<HookInfo> {
+    REGISTRY
+        .iter()
+        .filter(|hook| !matches!(hook.runner, Runner::Disabled { .. }))
+        .map(|hook| hook.info)
+        .collect()
+}
+
+/// A hook run after the query is filtered, in the order of the rule.
+#[derive(Clone, Copy)]
+pub(crate) enum Rewriter {
+    Plain(HookFn),
+    Chained(ChainedHookFn),
+}
+
+impl Rewriter {
+    pub fn run(self, input: &url::Url, args: &HookArgs) -> anyhow::Result<HookOutcome> {
+        match self {
+            Self::Plain(hook_fn) => hook_fn(input, args).map(HookOutcome::Continue),
+            Self::Chained(hook_fn) => hook_fn(input, args),
+        }
+    }
+}
+
+pub(crate) fn rewrite(name: &str) -> Option<Rewriter> {
+    match BY_NAME.get(name)?.runner {
+        Runner::Rewrite(hook_fn) => Some(Rewriter::Plain(hook_fn)),
+        Runner::Chained(hook_fn) => Some(Rewriter::Chained(hook_fn)),
+        _ => None,
+    }
+}
+
+pub(crate) fn unwrap(name: &str) -> Option<HookFn> {
+    match BY_NAME.get(name)?.runner {
+        Runner::Unwrap(hook_fn) => Some(hook_fn),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "http")]
+pub(crate) fn network(name: &str) -> Option<AsyncHookFn> {
+    match BY_NAME.get(name)?.runner {
+        Runner::Network(hook_fn) => Some(hook_fn),
+        _ => None,
+    }
+}
+
+/// Why a hook referenced by the ruleset is rejected.
+pub(crate) enum HookProblem {
+    Unknown {
+        suggestion: Option<&'static str>,
+    },
+    InvalidArgs(String),
+    /// The hook exists, but this build lacks `feature`. Its arguments are valid.
+    NotCompiled {
+        feature: &'static str,
+    },
+}
+
+/// Check the hook exists and its arguments match its spec.
+pub(crate) fn check(name: &str, args: &HookArgs) -> Result<(), HookProblem> {
+    let Some(hook) = BY_NAME.get(name) else {
+        let suggestion = BY_NAME
+            .keys()
+            .map(|known| (edit_distance(name, known), *known))
+            .filter(|(distance, _)| *distance <= 3)
+            .min()
+            .map(|(_, known)| known);
+        return Err(HookProblem::Unknown { suggestion });
+    };
+    let spec = hook.info.args;
+
+    if let Some(missing) = spec.required_args.iter().find(|a| !args.contains_key(**a)) {
+        return Err(HookProblem::InvalidArgs(format!(
+            "missing required argument '{missing}'"
+        )));
+    }
+    let mut unknown: Vec<_> = args
+        .keys()
+        .filter(|a| !spec.required_args.contains(&a.as_str()))
+        .filter(|a| !spec.optional_args.contains(&a.as_str()))
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort();
+        return Err(HookProblem::InvalidArgs(format!(
+            "unknown argument '{}'",
+            unknown[0]
+        )));
+    }
+
+    match hook.runner {
+        Runner::Disabled { feature } => Err(HookProblem::NotCompiled { feature }),
+        _ => Ok(()),
+    }
+}
 
+/// Levenshtein distance by characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != *cb);
+            curr[j + 1] = substitute.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+#[cfg(feature = "hook-bilibili")]
+lazy_static! {
     // Internal
     static ref TRANSLATE: HashMap<char, u64> = {
         TABLE
@@ -22,72 +315,162 @@ lazy_static! {
     };
 }
 
+#[cfg(feature = "hook-bilibili")]
 const TABLE: &str = "fZodR9XQDSUm21yCkr6zBqiveYah8bt4xsWpHnJE7jL5VG3guMTKNPAwcF";
-#[cfg(feature = "bilibili_hooks")]
+#[cfg(feature = "hook-bilibili")]
 const SELECT: [usize; 6] = [11, 10, 3, 8, 4, 6];
-#[cfg(feature = "bilibili_hooks")]
+#[cfg(feature = "hook-bilibili")]
 const XOR: u64 = 177451812;
-#[cfg(feature = "bilibili_hooks")]
+#[cfg(feature = "hook-bilibili")]
 const ADD: u64 = 8728348608;
 
-#[cfg(feature = "bilibili_hooks")]
-fn bv_to_av(input: &url::Url) -> anyhow::Result<url::Url> {
-    if input.domain().is_none() {
+const BV_TO_AV: HookInfo = HookInfo::new("bv_to_av", "Convert bilibili BV video ids to AV ids");
+
+#[cfg(feature = "hook-bilibili")]
+fn bv_to_av(input: &url::Url, _: &HookArgs) -> anyhow::Result<url::Url> {
+    if text::lookup_domain(input).is_none() {
         anyhow::bail!("domain is empty");
     }
 
-    if input.path_segments().is_none() {
+    let Some(segments) = input.path_segments() else {
         anyhow::bail!("url doesn't have path segment");
+    };
+    let segments: Vec<_> = segments.collect();
+    // other pages, like posts and dynamics, have no video id to convert
+    if segments.first() != Some(&"video") {
+        return Ok(input.clone());
     }
-
-    let segments: Vec<_> = input.path_segments().unwrap().collect();
-    if segments.len() < 2 {
+    let Some(bvid) = segments.get(1) else {
         anyhow::bail!("path segment is too short: {input}");
-    }
-    if segments[0] != "video" {
-        anyhow::bail!("{input} is not a video URL");
-    }
-    if !segments[1].starts_with("BV") || !segments.len() == 12 {
+    };
+    let chars: Vec<char> = bvid.chars().collect();
+    if !bvid.starts_with("BV") || chars.len() != 12 {
         anyhow::bail!("{input} is not a valid BV-encoded video URL");
     }
 
-    let chars: Vec<char> = segments[1].chars().collect();
-    let result: u64 = (0..6).fold(0, |acc, i| {
-        let select = SELECT[i];
-        let char = chars[select];
-        let translated = TRANSLATE[&char];
-        acc + translated * (58_u64.pow(i as u32))
+    let result = SELECT.iter().enumerate().try_fold(0, |acc, (i, &select)| {
+        let translated = TRANSLATE.get(&chars[select])?;
+        Some(acc + translated * 58_u64.pow(i as u32))
     });
-    let avid = (result - ADD) ^ XOR;
+    let Some(avid) = result.and_then(|result| result.checked_sub(ADD)) else {
+        anyhow::bail!("{input} is not a valid BV-encoded video URL");
+    };
+    let avid = avid ^ XOR;
 
     let mut new_url = input.clone();
     new_url
         .path_segments_mut()
-        .unwrap()
+        .map_err(|_| anyhow::anyhow!("{input} cannot be a base"))?
         .clear()
-        .extend([segments[0], &format!("av{avid}"), ""]);
+        .extend(["video", &format!("av{avid}"), ""]);
 
     Ok(new_url)
 }
 
-#[cfg(feature = "bilibili_hooks")]
+#[cfg(feature = "hook-bilibili")]
 #[test]
 fn test_bv_to_av() {
     let a = url::Url::parse("https://www.bilibili.com/video/BV1nY411r7o1/?p=1").unwrap();
     assert_eq!(
-        bv_to_av(&a).unwrap().to_string(),
+        bv_to_av(&a, &HookArgs::new()).unwrap().to_string(),
         "https://www.bilibili.com/video/av267692137/?p=1"
     );
     let b = url::Url::parse("https://www.bilibili.com/video/av747880465?p=1").unwrap();
-    assert!(bv_to_av(&b).is_err());
+    assert!(bv_to_av(&b, &HookArgs::new()).is_err());
+    // malformed ids are rejected instead of indexing out of the id or the table
+    for id in ["BV1", "BV1nY411r7o1extra", "BV1000000000", "BV1nY411r7o!"] {
+        let url = url::Url::parse(&format!("https://www.bilibili.com/video/{id}")).unwrap();
+        assert!(bv_to_av(&url, &HookArgs::new()).is_err(), "{id}");
+    }
+    let c = url::Url::parse("https://t.bilibili.com/912345678901234567").unwrap();
+    assert_eq!(bv_to_av(&c, &HookArgs::new()).unwrap(), c);
 }
 
-fn fixup_twitter(input: &url::Url) -> anyhow::Result<url::Url> {
-    if input.domain().is_none() {
+const BILIBILI_MOBILE_TO_DESKTOP: HookInfo = HookInfo::new(
+    "bilibili_mobile_to_desktop",
+    "Move m.bilibili.com links to their desktop pages",
+);
+
+/// Move `m.bilibili.com` URLs to the desktop site. Videos and posts keep their path on
+/// `www`, while dynamics and user spaces have their own hosts. Other bilibili hosts are left
+/// as is.
+#[cfg(feature = "hook-bilibili")]
+fn bilibili_mobile_to_desktop(input: &url::Url, _: &HookArgs) -> anyhow::Result<url::Url> {
+    let Some(domain) = text::lookup_domain(input) else {
         anyhow::bail!("domain is empty");
+    };
+    if domain != "bilibili.com" && !domain.ends_with(".bilibili.com") {
+        anyhow::bail!("not a valid bilibili URL");
+    }
+    if domain != "m.bilibili.com" {
+        return Ok(input.clone());
+    }
+
+    let mut new_url = input.clone();
+    let segments: Vec<_> = input
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    match segments[..] {
+        ["dynamic", id, ..] => {
+            new_url.set_host(Some("t.bilibili.com"))?;
+            new_url.set_path(&format!("/{id}"));
+        }
+        ["space", mid, ..] => {
+            new_url.set_host(Some("space.bilibili.com"))?;
+            new_url.set_path(&format!("/{mid}"));
+        }
+        _ => new_url.set_host(Some("www.bilibili.com"))?,
+    }
+
+    Ok(new_url)
+}
+
+#[cfg(feature = "hook-bilibili")]
+#[test]
+fn test_bilibili_mobile_to_desktop() {
+    let rewrite = |url: &str| {
+        let url = url::Url::parse(url).unwrap();
+        bilibili_mobile_to_desktop(&url, &HookArgs::new()).map(|url| url.to_string())
+    };
+    for (mobile, desktop) in [
+        (
+            "https://m.bilibili.com/video/BV1nY411r7o1?p=2",
+            "https://www.bilibili.com/video/BV1nY411r7o1?p=2",
+        ),
+        (
+            "https://m.bilibili.com/opus/912345678901234567",
+            "https://www.bilibili.com/opus/912345678901234567",
+        ),
+        (
+            "https://m.bilibili.com/dynamic/912345678901234567",
+            "https://t.bilibili.com/912345678901234567",
+        ),
+        (
+            "https://m.bilibili.com/space/2",
+            "https://space.bilibili.com/2",
+        ),
+        (
+            "https://www.bilibili.com/video/BV1nY411r7o1",
+            "https://www.bilibili.com/video/BV1nY411r7o1",
+        ),
+    ] {
+        assert_eq!(rewrite(mobile).unwrap(), desktop);
     }
+    assert!(rewrite("https://m.example.com/video/BV1").is_err());
+}
 
-    let domain = input.domain().unwrap();
+const FIXUP_TWITTER: HookInfo = HookInfo::new(
+    "fixup_twitter",
+    "Move twitter and x.com links to their embed-friendly mirrors",
+);
+
+/// The mirror is the link to share, so the hooks after it are skipped.
+#[cfg(feature = "hook-twitter")]
+fn fixup_twitter(input: &url::Url, _: &HookArgs) -> anyhow::Result<HookOutcome> {
+    let Some(domain) = text::lookup_domain(input) else {
+        anyhow::bail!("domain is empty");
+    };
     let fixup_domain = match domain {
         "twitter.com" => "fxtwitter.com",
         "www.twitter.com" => "www.fxtwitter.com",
@@ -95,33 +478,806 @@ fn fixup_twitter(input: &url::Url) -> anyhow::Result<url::Url> {
         _ => anyhow::bail!("not a valid twitter URL"),
     };
     let mut new_url = input.clone();
-    new_url.set_host(Some(fixup_domain)).unwrap();
-    Ok(new_url)
+    new_url.set_host(Some(fixup_domain))?;
+    Ok(HookOutcome::Final(new_url))
 }
 
-fn fixup_zhihu(input: &url::Url) -> anyhow::Result<url::Url> {
-    if input.domain().is_none() {
-        anyhow::bail!("domain is empty");
-    }
+const FIXUP_ZHIHU: HookInfo =
+    HookInfo::new("fixup_zhihu", "Move zhihu answers to fxzhihu for embedding");
 
-    let domain = input.domain().unwrap();
-    let pattern = regex::Regex::new("zhihu\\.com").unwrap();
-    let fixup_domain = pattern.replace(domain, "fxzhihu.com");
+#[cfg(feature = "hook-zhihu")]
+fn fixup_zhihu(input: &url::Url, _: &HookArgs) -> anyhow::Result<url::Url> {
+    let Some(domain) = text::lookup_domain(input) else {
+        anyhow::bail!("domain is empty");
+    };
+    let fixup_domain = domain.replacen("zhihu.com", "fxzhihu.com", 1);
     let mut new_url = input.clone();
-    new_url.set_host(Some(&fixup_domain)).unwrap();
+    new_url.set_host(Some(&fixup_domain))?;
     Ok(new_url)
 }
 
+#[cfg(feature = "hook-zhihu")]
 #[test]
 fn test_fixup_zhihu() {
     let a = url::Url::parse("https://zhihu.com/abcdefg/?k=1").unwrap();
     assert_eq!(
-        fixup_zhihu(&a).unwrap().to_string(),
+        fixup_zhihu(&a, &HookArgs::new()).unwrap().to_string(),
         "https://fxzhihu.com/abcdefg/?k=1"
     );
     let b = url::Url::parse("https://zhuanlan.zhihu.com/abcdefg/?k=1").unwrap();
     assert_eq!(
-        fixup_zhihu(&b).unwrap().to_string(),
+        fixup_zhihu(&b, &HookArgs::new()).unwrap().to_string(),
         "https://zhuanlan.fxzhihu.com/abcdefg/?k=1"
     );
 }
+
+const SPOTIFY_CANONICAL: HookInfo = HookInfo::new(
+    "spotify_canonical",
+    "Move spotify links to open.spotify.com without locale prefix",
+);
+
+/// Move spotify URLs to `open.spotify.com` and drop the `intl-<lang>` locale path prefix.
+#[cfg(feature = "hook-spotify")]
+fn spotify_canonical(input: &url::Url, _: &HookArgs) -> anyhow::Result<url::Url> {
+    let Some(domain) = text::lookup_domain(input) else {
+        anyhow::bail!("domain is empty");
+    };
+    if domain != "spotify.com" && !domain.ends_with(".spotify.com") {
+        anyhow::bail!("not a valid spotify URL");
+    }
+
+    let mut new_url = input.clone();
+    new_url.set_host(Some("open.spotify.com"))?;
+    let segments: Vec<_> = input
+        .path_segments()
+        .map(|s| s.collect())
+        .unwrap_or_default();
+    if segments.first().is_some_and(|s| s.starts_with("intl-")) {
+        new_url
+            .path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("{input} cannot be a base"))?
+            .clear()
+            .extend(&segments[1..]);
+    }
+
+    Ok(new_url)
+}
+
+#[cfg(feature = "hook-spotify")]
+#[test]
+fn test_spotify_canonical() {
+    let a = url::Url::parse("https://open.spotify.com/intl-de/track/4uLU6hMCjMI75M1A2tKUQC?si=1")
+        .unwrap();
+    assert_eq!(
+        spotify_canonical(&a, &HookArgs::new()).unwrap().to_string(),
+        "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=1"
+    );
+    let b = url::Url::parse("https://play.spotify.com/album/1").unwrap();
+    assert_eq!(
+        spotify_canonical(&b, &HookArgs::new()).unwrap().to_string(),
+        "https://open.spotify.com/album/1"
+    );
+    let c = url::Url::parse("https://example.com/intl-de/track/1").unwrap();
+    assert!(spotify_canonical(&c, &HookArgs::new()).is_err());
+}
+
+const TIKTOK_CANONICAL: HookInfo = HookInfo::new(
+    "tiktok_canonical",
+    "Reduce TikTok videos to /@user/video/<id>, optionally on another host",
+)
+.with_args(HookSpec::OPTIONAL_HOST);
+
+/// Reduce TikTok video URLs to `/@user/video/<id>`, dropping the trailing slug. With the
+/// `host` argument, the URL is moved to the given host, like a ProxiTok instance.
+#[cfg(feature = "hook-tiktok")]
+fn tiktok_canonical(input: &url::Url, args: &HookArgs) -> anyhow::Result<url::Url> {
+    let Some(domain) = text::lookup_domain(input) else {
+        anyhow::bail!("domain is empty");
+    };
+    if domain != "tiktok.com" && !domain.ends_with(".tiktok.com") {
+        anyhow::bail!("not a valid tiktok URL");
+    }
+
+    let mut new_url = input.clone();
+    let segments: Vec<_> = input
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    if let [user, kind @ ("video" | "photo"), id, ..] = segments[..] {
+        if user.starts_with('@') {
+            new_url.set_path(&format!("/{user}/{kind}/{id}"));
+        }
+    }
+    if let Some(host) = args.get("host") {
+        new_url.set_host(Some(host))?;
+    }
+
+    Ok(new_url)
+}
+
+#[cfg(feature = "hook-tiktok")]
+#[test]
+fn test_tiktok_canonical() {
+    let a = url::Url::parse("https://www.tiktok.com/@user/video/123/slug-text?lang=en").unwrap();
+    assert_eq!(
+        tiktok_canonical(&a, &HookArgs::new()).unwrap().to_string(),
+        "https://www.tiktok.com/@user/video/123?lang=en"
+    );
+    let b = url::Url::parse("https://www.tiktok.com/@user").unwrap();
+    let args = HookArgs::from([("host".to_string(), "proxitok.example".to_string())]);
+    assert_eq!(
+        tiktok_canonical(&b, &args).unwrap().to_string(),
+        "https://proxitok.example/@user"
+    );
+    // a host that can't be set is an error of the hook
+    let args = HookArgs::from([("host".to_string(), "proxi tok.example".to_string())]);
+    assert!(tiktok_canonical(&b, &args).is_err());
+}
+
+const INSTAGRAM_CANONICAL: HookInfo = HookInfo::new(
+    "instagram_canonical",
+    "Drop img_index=1 from Instagram posts, optionally moving them to another host",
+)
+.with_args(HookSpec::OPTIONAL_HOST);
+
+/// Drop the redundant `img_index=1` of Instagram posts. With the `host` argument, posts are
+/// moved to the given host, like a ddinstagram instance. Profile URLs are kept untouched.
+#[cfg(feature = "hook-instagram")]
+fn instagram_canonical(input: &url::Url, args: &HookArgs) -> anyhow::Result<url::Url> {
+    let Some(domain) = text::lookup_domain(input) else {
+        anyhow::bail!("domain is empty");
+    };
+    if domain != "instagram.com" && !domain.ends_with(".instagram.com") {
+        anyhow::bail!("not a valid instagram URL");
+    }
+
+    let is_post = input
+        .path_segments()
+        .and_then(|mut s| s.next())
+        .is_some_and(|s| matches!(s, "p" | "reel" | "reels" | "tv"));
+    if !is_post {
+        return Ok(input.clone());
+    }
+
+    let mut new_url = input.clone();
+    if let Some(query) = input.query() {
+        let kept: Vec<_> = query.split('&').filter(|p| *p != "img_index=1").collect();
+        new_url.set_query(
+            Some(&kept.join("&"))
+                .filter(|q| !q.is_empty())
+                .map(String::as_str),
+        );
+    }
+    if let Some(host) = args.get("host") {
+        new_url.set_host(Some(host))?;
+    }
+
+    Ok(new_url)
+}
+
+#[cfg(feature = "hook-instagram")]
+#[test]
+fn test_instagram_canonical() {
+    let args = HookArgs::from([("host".to_string(), "ddinstagram.com".to_string())]);
+    let a = url::Url::parse("https://www.instagram.com/p/Cabc/?img_index=1").unwrap();
+    assert_eq!(
+        instagram_canonical(&a, &HookArgs::new())
+            .unwrap()
+            .to_string(),
+        "https://www.instagram.com/p/Cabc/"
+    );
+    assert_eq!(
+        instagram_canonical(&a, &args).unwrap().to_string(),
+        "https://ddinstagram.com/p/Cabc/"
+    );
+    let b = url::Url::parse("https://www.instagram.com/reel/Cabc/?img_index=2").unwrap();
+    assert_eq!(
+        instagram_canonical(&b, &args).unwrap().to_string(),
+        "https://ddinstagram.com/reel/Cabc/?img_index=2"
+    );
+    let c = url::Url::parse("https://www.instagram.com/someone/").unwrap();
+    assert_eq!(instagram_canonical(&c, &args).unwrap(), c);
+}
+
+const FACEBOOK_CANONICAL: HookInfo = HookInfo::new(
+    "facebook_canonical",
+    "Move mobile Facebook links to www and story.php links to their post URL",
+);
+
+/// Move `m.facebook.com` and `mbasic.facebook.com` URLs to `www.facebook.com`, and rewrite
+/// `story.php?story_fbid=<post>&id=<owner>` into `/<owner>/posts/<post>` when both ids are
+/// numeric. Other Facebook URLs are kept untouched.
+#[cfg(feature = "hook-facebook")]
+fn facebook_canonical(input: &url::Url, _: &HookArgs) -> anyhow::Result<url::Url> {
+    let Some(domain) = text::lookup_domain(input) else {
+        anyhow::bail!("domain is empty");
+    };
+    if domain != "facebook.com" && !domain.ends_with(".facebook.com") {
+        anyhow::bail!("not a valid facebook URL");
+    }
+
+    let mut new_url = input.clone();
+    if matches!(domain, "m.facebook.com" | "mbasic.facebook.com") {
+        new_url.set_host(Some("www.facebook.com"))?;
+    }
+    if input.path() != "/story.php" {
+        return Ok(new_url);
+    }
+
+    let numeric = |key: &str| {
+        input
+            .query_pairs()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+            .filter(|v| !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()))
+    };
+    let (Some(post), Some(owner)) = (numeric("story_fbid"), numeric("id")) else {
+        return Ok(new_url);
+    };
+    new_url.set_path(&format!("/{owner}/posts/{post}"));
+    let kept: Vec<_> = (input.query().unwrap_or_default().split('&'))
+        .filter(|p| !matches!(p.split('=').next(), Some("story_fbid" | "id")))
+        .collect();
+    new_url.set_query(
+        Some(&kept.join("&"))
+            .filter(|q| !q.is_empty())
+            .map(String::as_str),
+    );
+
+    Ok(new_url)
+}
+
+#[cfg(feature = "hook-facebook")]
+#[test]
+fn test_facebook_canonical() {
+    let canonical = |url: &str| {
+        facebook_canonical(&url::Url::parse(url).unwrap(), &HookArgs::new()).map(|u| u.to_string())
+    };
+    assert_eq!(
+        canonical("https://www.facebook.com/story.php?story_fbid=123&id=456").unwrap(),
+        "https://www.facebook.com/456/posts/123"
+    );
+    assert_eq!(
+        canonical("https://m.facebook.com/story.php?id=456&story_fbid=123&locale=fr_FR").unwrap(),
+        "https://www.facebook.com/456/posts/123?locale=fr_FR"
+    );
+    assert_eq!(
+        canonical("https://mbasic.facebook.com/groups/g/permalink/123/").unwrap(),
+        "https://www.facebook.com/groups/g/permalink/123/"
+    );
+    // ids that are not plain numbers are left as is
+    for url in [
+        "https://www.facebook.com/story.php?story_fbid=pfbid02abc&id=456",
+        "https://www.facebook.com/story.php?story_fbid=123",
+        "https://web.facebook.com/someone",
+    ] {
+        assert_eq!(canonical(url).unwrap(), url);
+    }
+    assert!(canonical("https://facebook.example/story.php?story_fbid=1&id=2").is_err());
+}
+
+const UNWRAP_STEAM_LINKFILTER: HookInfo = HookInfo::new(
+    "unwrap_steam_linkfilter",
+    "Extract the target of Steam linkfilter links",
+);
+
+/// Extract the target of Steam `steamcommunity.com/linkfilter/?u=<target>` links. The older
+/// form uses `url=` instead of `u=`. Other steam URLs are kept untouched.
+#[cfg(feature = "hook-steam")]
+fn unwrap_steam_linkfilter(input: &url::Url, _: &HookArgs) -> anyhow::Result<url::Url> {
+    if text::lookup_domain(input) != Some("steamcommunity.com") {
+        anyhow::bail!("not a valid steam community URL");
+    }
+    if input.path().trim_end_matches('/') != "/linkfilter" {
+        return Ok(input.clone());
+    }
+
+    let find = |name: &str| {
+        input
+            .query_pairs()
+            .find(|(k, v)| k == name && !v.is_empty())
+            .map(|(_, v)| v)
+    };
+    let Some(target) = find("u").or_else(|| find("url")) else {
+        anyhow::bail!("linkfilter URL has no target");
+    };
+    let target = url::Url::parse(&target)?;
+    if !matches!(target.scheme(), "http" | "https") {
+        anyhow::bail!("linkfilter target {target} is not a web URL");
+    }
+
+    Ok(target)
+}
+
+#[cfg(feature = "hook-steam")]
+#[test]
+fn test_unwrap_steam_linkfilter() {
+    let a = url::Url::parse(
+        "https://steamcommunity.com/linkfilter/?u=https%3A%2F%2Fexample.com%2Fa%3Futm_source%3Dsteam",
+    )
+    .unwrap();
+    assert_eq!(
+        unwrap_steam_linkfilter(&a, &HookArgs::new())
+            .unwrap()
+            .to_string(),
+        "https://example.com/a?utm_source=steam"
+    );
+    let b =
+        url::Url::parse("https://steamcommunity.com/linkfilter/?url=https://example.com/").unwrap();
+    assert_eq!(
+        unwrap_steam_linkfilter(&b, &HookArgs::new())
+            .unwrap()
+            .to_string(),
+        "https://example.com/"
+    );
+    let c = url::Url::parse("https://steamcommunity.com/linkfilter/?x=1").unwrap();
+    assert!(unwrap_steam_linkfilter(&c, &HookArgs::new()).is_err());
+    let d =
+        url::Url::parse("https://steamcommunity.com/linkfilter/?u=javascript:alert(1)").unwrap();
+    assert!(unwrap_steam_linkfilter(&d, &HookArgs::new()).is_err());
+    let e = url::Url::parse("https://steamcommunity.com/id/someone").unwrap();
+    assert_eq!(unwrap_steam_linkfilter(&e, &HookArgs::new()).unwrap(), e);
+}
+
+const YOUTUBE_UNWRAP: HookInfo = HookInfo::new(
+    "youtube_unwrap",
+    "Extract the target of YouTube /redirect and /attribution_link links",
+);
+
+/// Extract the external target of `youtube.com/redirect?q=<target>` links, and the video of
+/// `youtube.com/attribution_link?u=/watch%3Fv%3D...` share links. Other YouTube URLs are
+/// kept untouched.
+#[cfg(feature = "hook-youtube")]
+fn youtube_unwrap(input: &url::Url, _: &HookArgs) -> anyhow::Result<url::Url> {
+    if !matches!(
+        text::lookup_domain(input),
+        Some("youtube.com" | "www.youtube.com" | "m.youtube.com")
+    ) {
+        anyhow::bail!("not a valid youtube URL");
+    }
+
+    let find = |name: &str| {
+        input
+            .query_pairs()
+            .find(|(k, v)| k == name && !v.is_empty())
+            .map(|(_, v)| v)
+    };
+    let target = match input.path().trim_end_matches('/') {
+        "/redirect" => {
+            let Some(target) = find("q") else {
+                anyhow::bail!("redirect URL has no target");
+            };
+            // the description links are sometimes encoded twice
+            if target.contains("://") {
+                url::Url::parse(&target)?
+            } else {
+                url::Url::parse(&crate::text::percent_decode(&target))?
+            }
+        }
+        "/attribution_link" => {
+            let Some(target) = find("u") else {
+                anyhow::bail!("attribution link has no target");
+            };
+            if !target.starts_with('/') || target.starts_with("//") {
+                anyhow::bail!("attribution link target {target} is not a YouTube path");
+            }
+            url::Url::parse("https://www.youtube.com")?.join(&target)?
+        }
+        _ => return Ok(input.clone()),
+    };
+    if !matches!(target.scheme(), "http" | "https") {
+        anyhow::bail!("redirect target {target} is not a web URL");
+    }
+
+    Ok(target)
+}
+
+#[cfg(feature = "hook-youtube")]
+#[test]
+fn test_youtube_unwrap() {
+    let unwrap = |url: &str| youtube_unwrap(&url::Url::parse(url).unwrap(), &HookArgs::new());
+    assert_eq!(
+        unwrap("https://www.youtube.com/redirect?event=video_description&redir_token=QUFF&q=https%3A%2F%2Fexample.com%2Fa%3Futm_source%3Dyt")
+            .unwrap()
+            .as_str(),
+        "https://example.com/a?utm_source=yt"
+    );
+    assert_eq!(
+        unwrap("https://www.youtube.com/redirect?q=https%253A%252F%252Fexample.com%252Fb")
+            .unwrap()
+            .as_str(),
+        "https://example.com/b"
+    );
+    assert_eq!(
+        unwrap("https://www.youtube.com/attribution_link?a=abc&u=%2Fwatch%3Fv%3DdQw4w9WgXcQ%26feature%3Dshare")
+            .unwrap()
+            .as_str(),
+        "https://www.youtube.com/watch?v=dQw4w9WgXcQ&feature=share"
+    );
+    assert!(unwrap("https://www.youtube.com/redirect?q=javascript%3Aalert(1)").is_err());
+    assert!(unwrap("https://www.youtube.com/attribution_link?u=%2F%2Fevil.test%2F").is_err());
+    assert!(unwrap("https://www.youtube.com/redirect?event=x").is_err());
+    let watch = url::Url::parse("https://www.youtube.com/watch?v=dQw4w9WgXcQ").unwrap();
+    assert_eq!(youtube_unwrap(&watch, &HookArgs::new()).unwrap(), watch);
+}
+
+const EBAY_CANONICAL: HookInfo = HookInfo::new(
+    "ebay_canonical",
+    "Reduce eBay item paths to /itm/<item id>, dropping the title slug",
+);
+
+/// Drop the title slug of eBay item URLs, `/itm/<slug>/<id>` becomes `/itm/<id>`. Other
+/// eBay URLs are kept untouched.
+#[cfg(feature = "hook-ebay")]
+fn ebay_canonical(input: &url::Url, _: &HookArgs) -> anyhow::Result<url::Url> {
+    let Some(domain) = text::lookup_domain(input) else {
+        anyhow::bail!("domain is empty");
+    };
+    if !domain.starts_with("ebay.") && !domain.contains(".ebay.") {
+        anyhow::bail!("not a valid ebay URL");
+    }
+
+    let segments: Vec<_> = input
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let item = match segments.as_slice() {
+        ["itm", .., id] if id.bytes().all(|b| b.is_ascii_digit()) => *id,
+        _ => return Ok(input.clone()),
+    };
+
+    let mut new_url = input.clone();
+    new_url.set_path(&format!("/itm/{item}"));
+    Ok(new_url)
+}
+
+#[cfg(feature = "hook-ebay")]
+#[test]
+fn test_ebay_canonical() {
+    let canonical = |url: &str| ebay_canonical(&url::Url::parse(url).unwrap(), &HookArgs::new());
+    assert_eq!(
+        canonical(
+            "https://www.ebay.com/itm/Vintage-Camera-Lens-50mm/254789123456?hash=item3b5a:g:abc"
+        )
+        .unwrap()
+        .as_str(),
+        "https://www.ebay.com/itm/254789123456?hash=item3b5a:g:abc"
+    );
+    assert_eq!(
+        canonical("https://www.ebay.co.uk/itm/254789123456/")
+            .unwrap()
+            .as_str(),
+        "https://www.ebay.co.uk/itm/254789123456"
+    );
+    let search = url::Url::parse("https://www.ebay.de/sch/i.html?_nkw=lens").unwrap();
+    assert_eq!(ebay_canonical(&search, &HookArgs::new()).unwrap(), search);
+    let slug = url::Url::parse("https://www.ebay.com/itm/Vintage-Camera").unwrap();
+    assert_eq!(ebay_canonical(&slug, &HookArgs::new()).unwrap(), slug);
+    assert!(canonical("https://example.com/itm/1").is_err());
+}
+
+const ALIEXPRESS_SHARE_UNWRAP: HookInfo = HookInfo::new(
+    "aliexpress_share_unwrap",
+    "Extract the target of AliExpress share.htm links",
+);
+
+/// Extract the `redirectUrl` of `star.aliexpress.com/share/share.htm` share links. Other
+/// AliExpress URLs are kept untouched.
+#[cfg(feature = "hook-aliexpress")]
+fn aliexpress_share_unwrap(input: &url::Url, _: &HookArgs) -> anyhow::Result<url::Url> {
+    let Some(domain) = text::lookup_domain(input) else {
+        anyhow::bail!("domain is empty");
+    };
+    if domain != "aliexpress.com" && !domain.ends_with(".aliexpress.com") {
+        anyhow::bail!("not a valid aliexpress URL");
+    }
+    if input.path() != "/share/share.htm" {
+        return Ok(input.clone());
+    }
+
+    let Some(target) = input
+        .query_pairs()
+        .find(|(k, v)| k == "redirectUrl" && !v.is_empty())
+        .map(|(_, v)| v)
+    else {
+        anyhow::bail!("share link has no target");
+    };
+    // the app sometimes encodes the target twice
+    let target = if target.contains("://") {
+        url::Url::parse(&target)?
+    } else {
+        url::Url::parse(&text::percent_decode(&target))?
+    };
+    if !matches!(target.scheme(), "http" | "https") {
+        anyhow::bail!("share link target {target} is not a web URL");
+    }
+
+    Ok(target)
+}
+
+#[cfg(feature = "hook-aliexpress")]
+#[test]
+fn test_aliexpress_share_unwrap() {
+    let unwrap =
+        |url: &str| aliexpress_share_unwrap(&url::Url::parse(url).unwrap(), &HookArgs::new());
+    assert_eq!(
+        unwrap("https://star.aliexpress.com/share/share.htm?platform=AE&businessType=ProductDetail&redirectUrl=https%3A%2F%2Fwww.aliexpress.com%2Fitem%2F1005004.html%3FsourceType%3D1%26spm%3Da2g0o")
+            .unwrap()
+            .as_str(),
+        "https://www.aliexpress.com/item/1005004.html?sourceType=1&spm=a2g0o"
+    );
+    assert_eq!(
+        unwrap("https://star.aliexpress.com/share/share.htm?redirectUrl=https%253A%252F%252Fm.aliexpress.com%252Fitem%252F1.html")
+            .unwrap()
+            .as_str(),
+        "https://m.aliexpress.com/item/1.html"
+    );
+    assert!(unwrap("https://star.aliexpress.com/share/share.htm?platform=AE").is_err());
+    assert!(unwrap(
+        "https://star.aliexpress.com/share/share.htm?redirectUrl=javascript%3Aalert(1)"
+    )
+    .is_err());
+    let item = url::Url::parse("https://www.aliexpress.com/item/1005004.html").unwrap();
+    assert_eq!(
+        aliexpress_share_unwrap(&item, &HookArgs::new()).unwrap(),
+        item
+    );
+}
+
+const TAOBAO_CANONICAL: HookInfo = HookInfo::new(
+    "taobao_canonical",
+    "Reduce Taobao and Tmall item URLs to item.htm?id=<item id>",
+);
+
+/// Keep only the `id` of Taobao and Tmall `item.htm` URLs, whatever else the share links
+/// carry. Other Taobao and Tmall URLs are kept untouched.
+#[cfg(feature = "hook-taobao")]
+fn taobao_canonical(input: &url::Url, _: &HookArgs) -> anyhow::Result<url::Url> {
+    let Some(domain) = text::lookup_domain(input) else {
+        anyhow::bail!("domain is empty");
+    };
+    let is_shop = |base: &str| domain == base || domain.ends_with(&format!(".{base}"));
+    if !is_shop("taobao.com") && !is_shop("tmall.com") {
+        anyhow::bail!("not a valid taobao URL");
+    }
+    if input.path() != "/item.htm" {
+        return Ok(input.clone());
+    }
+
+    let Some(id) = input
+        .query_pairs()
+        .find(|(k, v)| k == "id" && !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()))
+        .map(|(_, v)| v)
+    else {
+        return Ok(input.clone());
+    };
+
+    let mut new_url = input.clone();
+    new_url.set_query(Some(&format!("id={id}")));
+    new_url.set_fragment(None);
+    Ok(new_url)
+}
+
+#[cfg(feature = "hook-taobao")]
+#[test]
+fn test_taobao_canonical() {
+    let canonical = |url: &str| taobao_canonical(&url::Url::parse(url).unwrap(), &HookArgs::new());
+    assert_eq!(
+        canonical("https://item.taobao.com/item.htm?ft=t&id=678901234567&skuId=5012#detail")
+            .unwrap()
+            .as_str(),
+        "https://item.taobao.com/item.htm?id=678901234567"
+    );
+    assert_eq!(
+        canonical("https://detail.tmall.com/item.htm?id=612345678901&sku_properties=1627207:28341")
+            .unwrap()
+            .as_str(),
+        "https://detail.tmall.com/item.htm?id=612345678901"
+    );
+    let shop = url::Url::parse("https://shop123.taobao.com/index.htm?spm=a").unwrap();
+    assert_eq!(taobao_canonical(&shop, &HookArgs::new()).unwrap(), shop);
+    let no_id = url::Url::parse("https://item.taobao.com/item.htm?id=abc").unwrap();
+    assert_eq!(taobao_canonical(&no_id, &HookArgs::new()).unwrap(), no_id);
+    assert!(canonical("https://taobao.example/item.htm?id=1").is_err());
+}
+
+const REDDIT_SHARE: HookInfo = HookInfo::new(
+    "reddit_share",
+    "Resolve Reddit /s/ share links into the post, optionally moving it to another host",
+)
+.with_args(HookSpec::OPTIONAL_HOST)
+.network();
+
+/// Resolve the personalized Reddit share link `/r/<sub>/s/<token>` into the real post.
+/// With the `host` argument, reddit URLs are moved to the given host like `old.reddit.com`.
+/// Only the share link is requested, unless the client given to the cleaner follows
+/// redirections.
+#[cfg(all(feature = "hook-reddit", feature = "http"))]
+fn reddit_share<'a>(
+    input: &'a url::Url,
+    args: &'a HookArgs,
+    cleaner: &'a crate::UrlCleaner,
+) -> HookFuture<'a> {
+    Box::pin(async move {
+        let is_reddit = |url: &url::Url| matches!(text::lookup_domain(url), Some(d) if d == "reddit.com" || d.ends_with(".reddit.com"));
+        if !is_reddit(input) {
+            anyhow::bail!("not a valid reddit URL");
+        }
+
+        let mut url = input.clone();
+        let segments: Vec<_> = input
+            .path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        if matches!(segments[..], ["r", _, "s", _]) {
+            // The body is never read, only the location is used
+            let request = cleaner.hook_client.get(input.clone());
+            let response = cleaner.send(request, input).await?.error_for_status()?;
+            url = crate::Resolved::from(&response).target;
+        }
+
+        if let Some(host) = args.get("host") {
+            if host != "reddit.com" && !host.ends_with(".reddit.com") {
+                anyhow::bail!("{host} is not a reddit host");
+            }
+            if is_reddit(&url) {
+                url.set_host(Some(host))?;
+            }
+        }
+
+        Ok(url)
+    })
+}
+
+#[cfg(test)]
+const TEST_TO_JAVASCRIPT: HookInfo = HookInfo::new("test_to_javascript", "");
+
+#[cfg(test)]
+fn test_to_javascript(_: &url::Url, _: &HookArgs) -> anyhow::Result<url::Url> {
+    Ok(url::Url::parse("javascript:alert(1)")?)
+}
+
+#[cfg(test)]
+const TEST_SET_PATH: HookInfo = HookInfo::new("test_set_path", "").with_args(HookSpec {
+    required_args: &["path"],
+    optional_args: &[],
+});
+
+#[cfg(test)]
+fn test_set_path(input: &url::Url, args: &HookArgs) -> anyhow::Result<url::Url> {
+    let mut new_url = input.clone();
+    new_url.set_path(&args["path"]);
+    Ok(new_url)
+}
+
+#[cfg(test)]
+const TEST_APPEND_PATH: HookInfo = HookInfo::new("test_append_path", "").with_args(HookSpec {
+    required_args: &["segment"],
+    optional_args: &[],
+});
+
+/// Append a segment to the path, so the order hooks run in shows in the URL. A segment
+/// already in the path is not added again, so the output is left as is by a second pass.
+#[cfg(test)]
+fn test_append_path(input: &url::Url, args: &HookArgs) -> anyhow::Result<url::Url> {
+    if input
+        .path()
+        .split('/')
+        .any(|segment| segment == args["segment"])
+    {
+        return Ok(input.clone());
+    }
+    let mut new_url = input.clone();
+    new_url.set_path(&format!(
+        "{}/{}",
+        input.path().trim_end_matches('/'),
+        args["segment"]
+    ));
+    Ok(new_url)
+}
+
+#[cfg(test)]
+const TEST_FINAL_PATH: HookInfo = HookInfo::new("test_final_path", "").with_args(HookSpec {
+    required_args: &["path"],
+    optional_args: &[],
+});
+
+#[cfg(test)]
+fn test_final_path(input: &url::Url, args: &HookArgs) -> anyhow::Result<HookOutcome> {
+    let mut new_url = input.clone();
+    new_url.set_path(&args["path"]);
+    Ok(HookOutcome::Final(new_url))
+}
+
+#[cfg(test)]
+const TEST_ADD_PARAM: HookInfo = HookInfo::new("test_add_param", "").with_args(HookSpec {
+    required_args: &["key", "value"],
+    optional_args: &[],
+});
+
+#[cfg(test)]
+fn test_add_param(input: &url::Url, args: &HookArgs) -> anyhow::Result<url::Url> {
+    let mut new_url = input.clone();
+    new_url
+        .query_pairs_mut()
+        .append_pair(&args["key"], &args["value"]);
+    Ok(new_url)
+}
+
+#[test]
+fn test_hook_specs() {
+    assert_eq!(BY_NAME.len(), REGISTRY.len());
+
+    let available = available();
+    assert_eq!(
+        available.iter().any(|hook| hook.name == "fixup_twitter"),
+        cfg!(feature = "hook-twitter")
+    );
+    assert_eq!(
+        available.iter().any(|hook| hook.name == "bv_to_av"),
+        cfg!(feature = "hook-bilibili")
+    );
+    assert_eq!(
+        available.iter().find(|hook| hook.name == "reddit_share"),
+        cfg!(all(feature = "hook-reddit", feature = "http")).then_some(&REDDIT_SHARE)
+    );
+    assert_eq!(
+        check("bv_to_av", &HookArgs::new()).is_ok(),
+        cfg!(feature = "hook-bilibili")
+    );
+
+    assert_eq!(edit_distance("fixup_twiter", "fixup_twitter"), 1);
+    assert_eq!(edit_distance("", "abc"), 3);
+}
+
+#[test]
+fn test_hook_features() {
+    // one line per built-in hook, the registry must follow the enabled features
+    let built_in = [
+        ("bv_to_av", cfg!(feature = "hook-bilibili")),
+        (
+            "bilibili_mobile_to_desktop",
+            cfg!(feature = "hook-bilibili"),
+        ),
+        ("fixup_twitter", cfg!(feature = "hook-twitter")),
+        ("fixup_zhihu", cfg!(feature = "hook-zhihu")),
+        ("spotify_canonical", cfg!(feature = "hook-spotify")),
+        ("tiktok_canonical", cfg!(feature = "hook-tiktok")),
+        ("instagram_canonical", cfg!(feature = "hook-instagram")),
+        ("facebook_canonical", cfg!(feature = "hook-facebook")),
+        ("unwrap_steam_linkfilter", cfg!(feature = "hook-steam")),
+        ("youtube_unwrap", cfg!(feature = "hook-youtube")),
+        ("ebay_canonical", cfg!(feature = "hook-ebay")),
+        ("aliexpress_share_unwrap", cfg!(feature = "hook-aliexpress")),
+        ("taobao_canonical", cfg!(feature = "hook-taobao")),
+        (
+            "reddit_share",
+            cfg!(all(feature = "hook-reddit", feature = "http")),
+        ),
+    ];
+    let expected: Vec<_> = (built_in.iter())
+        .filter(|(_, compiled)| *compiled)
+        .map(|(name, _)| *name)
+        .collect();
+    let compiled: Vec<_> = (available().into_iter())
+        .map(|hook| hook.name)
+        .filter(|name| !name.starts_with("test_"))
+        .collect();
+    assert_eq!(compiled, expected);
+
+    for (name, compiled) in built_in {
+        match check(name, &HookArgs::new()) {
+            Ok(()) => assert!(compiled, "{name}"),
+            Err(HookProblem::NotCompiled { feature }) => {
+                assert!(!compiled, "{name}");
+                assert!(feature == "http" || feature.starts_with("hook-"), "{name}");
+            }
+            Err(_) => panic!("{name} is rejected"),
+        }
+    }
+    // and list every hook of the registry
+    let test_hooks = (REGISTRY.iter())
+        .filter(|hook| hook.info.name.starts_with("test_"))
+        .count();
+    assert_eq!(REGISTRY.len(), built_in.len() + test_hooks);
+}
@@ -0,0 +1,90 @@
+//! Android `intent://` deep links, turned back into the web URL they wrap.
+//!
+//! A link looks like `intent://host/path?query#Intent;scheme=https;package=...;end`, and
+//! may carry the web page to open without the app in `S.browser_fallback_url`.
+
+use url::Url;
+
+use crate::{text, UrlCleanError};
+
+/// Whether the input uses the `intent:` scheme.
+pub(crate) fn is_intent(input: &str) -> bool {
+    input
+        .get(..7)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("intent:"))
+}
+
+/// The web URL of the intent link: the embedded authority, path and query under the
+/// declared scheme, or else the browser fallback URL.
+pub(crate) fn resolve(input: &str) -> Result<Url, UrlCleanError> {
+    let malformed = |message: &str| UrlCleanError::MalformedIntent(message.to_string());
+    let rest = input.get(7..).unwrap_or_default();
+    let Some((target, block)) = rest.rsplit_once("#Intent;") else {
+        return Err(malformed("no `#Intent;...;end` block"));
+    };
+    let Some(block) = block.strip_suffix("end") else {
+        return Err(malformed("the `#Intent` block doesn't end with `end`"));
+    };
+
+    let mut scheme = None;
+    let mut fallback = None;
+    for extra in block.split(';').filter(|extra| !extra.is_empty()) {
+        let Some((key, value)) = extra.split_once('=') else {
+            return Err(malformed("entry of the `#Intent` block is not `key=value`"));
+        };
+        match key {
+            "scheme" => scheme = Some(value),
+            "S.browser_fallback_url" => fallback = Some(text::percent_decode(value)),
+            _ => {}
+        }
+    }
+
+    let web = |url: Url| matches!(url.scheme(), "http" | "https").then_some(url);
+    let embedded = target
+        .strip_prefix("//")
+        .filter(|target| !target.is_empty())
+        .zip(scheme.filter(|scheme| matches!(*scheme, "http" | "https")))
+        .and_then(|(target, scheme)| Url::parse(&format!("{scheme}://{target}")).ok())
+        .and_then(web);
+    embedded
+        .or_else(|| fallback.and_then(|url| Url::parse(&url).ok()).and_then(web))
+        .ok_or_else(|| malformed("neither a web target nor a browser fallback URL"))
+}
+
+#[test]
+fn test_resolve() {
+    let url = resolve(
+        "intent://www.bilibili.com/video/BV1nY411r7o1?share_source=copy#Intent;scheme=https;package=tv.danmaku.bili;S.browser_fallback_url=https%3A%2F%2Fm.bilibili.com%2F;end",
+    )
+    .unwrap();
+    assert_eq!(
+        url.as_str(),
+        "https://www.bilibili.com/video/BV1nY411r7o1?share_source=copy"
+    );
+
+    // only the fallback is usable
+    for input in [
+        "intent:#Intent;package=com.example;S.browser_fallback_url=https%3A%2F%2Fexample.com%2Fa%3Fid%3D1;end",
+        "intent://open#Intent;scheme=exampleapp;S.browser_fallback_url=https%3A%2F%2Fexample.com%2Fa%3Fid%3D1;end",
+    ] {
+        assert_eq!(
+            resolve(input).unwrap().as_str(),
+            "https://example.com/a?id=1"
+        );
+    }
+
+    for garbage in [
+        "intent://example.com/a",
+        "intent://example.com/a#Intent;scheme=https",
+        "intent://example.com/a#Intent;scheme;end",
+        "intent://example.com/a#Intent;package=com.example;end",
+        "intent:#Intent;S.browser_fallback_url=javascript%3Aalert(1);end",
+    ] {
+        assert!(
+            matches!(resolve(garbage), Err(UrlCleanError::MalformedIntent(_))),
+            "{garbage}"
+        );
+    }
+    assert!(is_intent("Intent://x"));
+    assert!(!is_intent("https://x"));
+}
@@ -0,0 +1,91 @@
+//! Read the redirection target from interstitial HTML pages.
+
+// the patterns below are constant, and compiled by the tests
+#![allow(clippy::unwrap_used)]
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use url::Url;
+
+/// Only this many bytes of the page are inspected.
+pub(crate) const MAX_BODY: usize = 64 * 1024;
+
+lazy_static! {
+    static ref TAG: Regex = Regex::new(r"(?i)<(?:meta|link)\b[^>]*>").unwrap();
+    static ref ATTR: Regex =
+        Regex::new(r#"(?i)([a-z:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap();
+    static ref REFRESH: Regex =
+        Regex::new(r"(?i)^\s*\d*\s*;\s*url\s*=\s*['\x22]?([^'\x22]+)").unwrap();
+}
+
+/// Find the target of the page: the first meta refresh, then the canonical link, then the
+/// `og:url` meta. Relative targets are resolved against `base`.
+pub(crate) fn extract_target(body: &str, base: &Url) -> Option<Url> {
+    let tags: Vec<Vec<(String, String)>> = TAG
+        .find_iter(body)
+        .map(|tag| {
+            ATTR.captures_iter(tag.as_str())
+                .map(|cap| {
+                    let value = cap
+                        .get(2)
+                        .or_else(|| cap.get(3))
+                        .or_else(|| cap.get(4))
+                        .map_or("", |m| m.as_str());
+                    (cap[1].to_ascii_lowercase(), unescape(value))
+                })
+                .collect()
+        })
+        .collect();
+    let attr = |tag: &[(String, String)], name: &str| {
+        tag.iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.to_string())
+    };
+    let find = |key: &str, value: &str, target: &str| {
+        tags.iter()
+            .filter(|tag| attr(tag, key).is_some_and(|v| v.eq_ignore_ascii_case(value)))
+            .find_map(|tag| attr(tag, target))
+    };
+
+    let refresh = find("http-equiv", "refresh", "content")
+        .and_then(|content| Some(REFRESH.captures(&content)?[1].trim().to_string()));
+    let target = refresh
+        .or_else(|| find("rel", "canonical", "href"))
+        .or_else(|| find("property", "og:url", "content"))?;
+    base.join(&target).ok()
+}
+
+/// Decode the entities that commonly appear in attribute values.
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[test]
+fn test_extract_target() {
+    let base = Url::parse("https://t.cn/A6abcdE").unwrap();
+    let extract = |body: &str| extract_target(body, &base).map(|u| u.to_string());
+
+    assert_eq!(
+        extract(
+            r#"<META HTTP-EQUIV="Refresh" CONTENT="3;URL='https://example.com/a?x=1&amp;y=2'">"#
+        ),
+        Some("https://example.com/a?x=1&y=2".to_string())
+    );
+    assert_eq!(
+        extract(
+            r#"<meta property="og:url" content="https://example.com/og">
+<link rel=canonical href="/canonical">"#
+        ),
+        Some("https://t.cn/canonical".to_string())
+    );
+    assert_eq!(
+        extract(r#"<meta property='og:url' content='https://example.com/og'>"#),
+        Some("https://example.com/og".to_string())
+    );
+    assert_eq!(extract("<html><body>nothing</body></html>"), None);
+}
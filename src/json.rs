@@ -0,0 +1,240 @@
+//! Clean the URLs found in the string values of a JSON document, like a webhook payload.
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::{text, UrlCleaner};
+
+/// Which strings of the document [`UrlCleaner::clear_json_offline`] cleans.
+#[derive(Clone, Debug)]
+pub struct JsonCleanOptions {
+    /// Only clean the strings of the object members whose key matches, like `(?i)url`.
+    /// Array elements go by the key of the array. Every string is considered when `None`.
+    pub keys: Option<Regex>,
+    /// Also clean the URLs found inside a longer text, like a message. Otherwise only strings
+    /// that are a whole `http`/`https` URL are cleaned.
+    pub rewrite_substrings: bool,
+    /// How many URLs are resolved at once by [`UrlCleaner::clear_json`].
+    pub concurrency: usize,
+}
+
+impl Default for JsonCleanOptions {
+    fn default() -> Self {
+        Self {
+            keys: None,
+            rewrite_substrings: false,
+            concurrency: 8,
+        }
+    }
+}
+
+/// What cleaning a JSON document did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JsonCleanSummary {
+    /// Number of URLs found in the selected strings.
+    pub urls: usize,
+    /// JSON pointers of the strings that are changed, in traversal order.
+    pub changed: Vec<String>,
+}
+
+/// The selected strings of the document, with their JSON pointer.
+fn strings<'a>(value: &'a mut Value, options: &JsonCleanOptions) -> Vec<(String, &'a mut String)> {
+    let mut found = Vec::new();
+    collect(value, String::new(), None, options, &mut found);
+    found
+}
+
+fn collect<'a>(
+    value: &'a mut Value,
+    pointer: String,
+    key: Option<&str>,
+    options: &JsonCleanOptions,
+    found: &mut Vec<(String, &'a mut String)>,
+) {
+    match value {
+        Value::String(string) => {
+            let selected = match (&options.keys, key) {
+                (None, _) => true,
+                (Some(keys), Some(key)) => keys.is_match(key),
+                (Some(_), None) => false,
+            };
+            if selected && string.contains("http") {
+                found.push((pointer, string));
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                collect(item, format!("{pointer}/{i}"), key, options, found);
+            }
+        }
+        Value::Object(members) => {
+            for (name, member) in members.iter_mut() {
+                let escaped = name.replace('~', "~0").replace('/', "~1");
+                collect(
+                    member,
+                    format!("{pointer}/{escaped}"),
+                    Some(name),
+                    options,
+                    found,
+                );
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+/// Number of URLs to clean in the string, a whole URL or the ones in the text.
+fn count_urls(string: &str, options: &JsonCleanOptions) -> usize {
+    if options.rewrite_substrings {
+        return text::find_urls(string).len();
+    }
+    let whole = url::Url::parse(string).is_ok_and(|url| matches!(url.scheme(), "http" | "https"));
+    usize::from(whole)
+}
+
+impl UrlCleaner {
+    /// Clean the URLs in the string values of the JSON document in place, without network
+    /// access. Other values, and strings that are not URLs, are left untouched. Strings that
+    /// fail to clean are kept as is.
+    pub fn clear_json_offline(
+        &self,
+        value: &mut Value,
+        options: &JsonCleanOptions,
+    ) -> JsonCleanSummary {
+        let mut summary = JsonCleanSummary::default();
+        for (pointer, string) in strings(value, options) {
+            let urls = count_urls(string, options);
+            if urls == 0 {
+                continue;
+            }
+            summary.urls += urls;
+            let cleaned = match options.rewrite_substrings {
+                true => Some(self.clear_text_offline(string)),
+                false => self.clear_offline(string).ok().map(String::from),
+            };
+            if let Some(cleaned) = cleaned.filter(|cleaned| cleaned != string) {
+                *string = cleaned;
+                summary.changed.push(pointer);
+            }
+        }
+        summary
+    }
+
+    /// Same as [`UrlCleaner::clear_json_offline`], but follow redirection like
+    /// [`UrlCleaner::clear`], resolving at most [`JsonCleanOptions::concurrency`] URLs at
+    /// once.
+    #[cfg(feature = "http")]
+    pub async fn clear_json(
+        &self,
+        value: &mut Value,
+        options: &JsonCleanOptions,
+    ) -> JsonCleanSummary {
+        use futures_util::StreamExt;
+
+        let mut summary = JsonCleanSummary::default();
+        let mut found = strings(value, options);
+        found.retain(|(_, string)| {
+            let urls = count_urls(string, options);
+            summary.urls += urls;
+            urls > 0
+        });
+
+        let inputs: Vec<String> = found.iter().map(|(_, string)| string.to_string()).collect();
+        let cleaned: Vec<Option<String>> = futures_util::stream::iter(inputs)
+            .map(|input| async move {
+                match options.rewrite_substrings {
+                    true => Some(self.clear_text(&input).await),
+                    false => self.clear(&input).await.ok().map(String::from),
+                }
+            })
+            .buffered(options.concurrency.max(1))
+            .collect()
+            .await;
+
+        for ((pointer, string), cleaned) in found.iter_mut().zip(cleaned) {
+            if let Some(cleaned) = cleaned.filter(|cleaned| cleaned != &**string) {
+                **string = cleaned;
+                summary.changed.push(std::mem::take(pointer));
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+fn fixture() -> Value {
+    serde_json::json!({
+        "id": 42,
+        "url": "https://example.com/a?id=1&utm_source=hook",
+        "clean": "https://example.com/b?id=2",
+        "links": [
+            "https://example.com/c?fbclid=x",
+            { "href": "https://example.com/d?utm_medium=m", "count": 3 },
+            null
+        ],
+        "text": "see https://example.com/e?utm_source=chat, thanks",
+        "not/a~url": "https://example.com/f?utm_campaign=c",
+        "name": "http is a protocol"
+    })
+}
+
+#[cfg(test)]
+const RULES: &str = "[\"example.com\"]\nban = [\"utm_.*\", \"fbclid\"]\n";
+
+#[test]
+fn test_clear_json() {
+    let cleaner = UrlCleaner::from_toml(RULES).unwrap();
+    let mut value = fixture();
+    let summary = cleaner.clear_json_offline(&mut value, &JsonCleanOptions::default());
+    assert_eq!(summary.urls, 5);
+    assert_eq!(
+        summary.changed,
+        ["/links/0", "/links/1/href", "/not~1a~0url", "/url"]
+    );
+    let mut expect = fixture();
+    expect["url"] = "https://example.com/a?id=1".into();
+    expect["links"][0] = "https://example.com/c".into();
+    expect["links"][1]["href"] = "https://example.com/d".into();
+    expect["not/a~url"] = "https://example.com/f".into();
+    assert_eq!(value, expect);
+
+    // URLs in the middle of a text are only cleaned on request
+    let mut value = fixture();
+    let options = JsonCleanOptions {
+        rewrite_substrings: true,
+        ..Default::default()
+    };
+    let summary = cleaner.clear_json_offline(&mut value, &options);
+    assert_eq!(summary.urls, 6);
+    assert!(summary.changed.contains(&"/text".to_string()));
+    assert_eq!(value["text"], "see https://example.com/e, thanks");
+    assert_eq!(value["name"], "http is a protocol");
+
+    // array elements go by the key of the array
+    let mut value = fixture();
+    let options = JsonCleanOptions {
+        keys: Some(Regex::new("^(?:url|links)$").unwrap()),
+        ..Default::default()
+    };
+    let summary = cleaner.clear_json_offline(&mut value, &options);
+    assert_eq!(summary.changed, ["/links/0", "/url"]);
+    assert_eq!(
+        value["links"][1]["href"],
+        "https://example.com/d?utm_medium=m"
+    );
+}
+
+#[cfg(feature = "http")]
+#[tokio::test]
+async fn test_clear_json_online() {
+    let cleaner = UrlCleaner::from_toml(RULES).unwrap();
+    let mut value = fixture();
+    let summary = cleaner
+        .clear_json(&mut value, &JsonCleanOptions::default())
+        .await;
+    assert_eq!(
+        summary.changed,
+        ["/links/0", "/links/1/href", "/not~1a~0url", "/url"]
+    );
+    assert_eq!(value["url"], "https://example.com/a?id=1");
+}
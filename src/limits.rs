@@ -0,0 +1,46 @@
+/// Limits bound the work spent on a single URL, so data-stuffed or malicious inputs can't
+/// make cleaning arbitrarily slow. Set with `UrlCleanerBuilder::limits`.
+///
+/// By default, URLs longer than 8 KiB are rejected and only the first 256 query parameters
+/// are examined. Use [`Limits::none`] to disable both.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Reject input URLs longer than this many bytes with `UrlCleanError::UrlTooLong`.
+    pub max_url_len: Option<usize>,
+    /// Examine at most this many query parameters. What happens to the others is decided by
+    /// [`Limits::on_too_many_params`].
+    pub max_params: Option<usize>,
+    pub on_too_many_params: ParamLimitPolicy,
+}
+
+/// What to do with a query having more parameters than [`Limits::max_params`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParamLimitPolicy {
+    /// Keep the parameters beyond the limit as is, without matching them against the rule.
+    /// The report flags the query as truncated.
+    #[default]
+    PassThrough,
+    /// Fail with `UrlCleanError::TooManyParams`.
+    Reject,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_url_len: Some(8 * 1024),
+            max_params: Some(256),
+            on_too_many_params: ParamLimitPolicy::default(),
+        }
+    }
+}
+
+impl Limits {
+    /// No limit at all.
+    pub fn none() -> Self {
+        Self {
+            max_url_len: None,
+            max_params: None,
+            on_too_many_params: ParamLimitPolicy::default(),
+        }
+    }
+}
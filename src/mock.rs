@@ -0,0 +1,191 @@
+//! A tiny HTTP server for tests that need redirection, without reaching the network.
+#![cfg_attr(not(test), allow(dead_code, clippy::unwrap_used))]
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// Response served for a path.
+#[derive(Clone, Default)]
+pub(crate) struct Route {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    pub delay: Duration,
+    /// ETag sent with the body, a request with a matching `If-None-Match` gets a 304
+    pub etag: Option<String>,
+}
+
+impl Route {
+    /// 302 to the given location.
+    pub fn redirect(location: &str) -> Self {
+        Self {
+            status: 302,
+            headers: vec![("Location".to_string(), location.to_string())],
+            ..Default::default()
+        }
+    }
+
+    pub fn ok(body: &str) -> Self {
+        Self {
+            status: 200,
+            body: body.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[cfg_attr(not(feature = "remote_rules"), allow(dead_code))]
+    pub fn etag(mut self, etag: &str) -> Self {
+        self.etag = Some(etag.to_string());
+        self
+    }
+}
+
+pub(crate) struct MockServer {
+    addr: SocketAddr,
+    routes: Arc<Mutex<HashMap<String, Route>>>,
+    /// `METHOD path` of every received request
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    /// Serve the given routes. Paths are matched exactly, including the query. A route keyed
+    /// by `METHOD path` takes precedence over the one keyed by the path only.
+    pub async fn start(routes: Vec<(&str, Route)>) -> Self {
+        let routes: Arc<Mutex<HashMap<String, Route>>> = Arc::new(Mutex::new(
+            routes
+                .into_iter()
+                .map(|(path, route)| (path.to_string(), route))
+                .collect(),
+        ));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let recorder = Arc::clone(&requests);
+        let served = Arc::clone(&routes);
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let routes = Arc::clone(&served);
+                let recorder = Arc::clone(&recorder);
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0; 1024];
+                    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut chunk).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&buf);
+                    let mut line = request.lines().next().unwrap_or_default().split(' ');
+                    let method = line.next().unwrap_or_default().to_string();
+                    let path = line.next().unwrap_or_default().to_string();
+                    let credentials = request
+                        .lines()
+                        .any(|header| header.to_ascii_lowercase().starts_with("authorization:"));
+                    recorder.lock().unwrap().push(match credentials {
+                        true => format!("{method} {path} with credentials"),
+                        false => format!("{method} {path}"),
+                    });
+
+                    let found = {
+                        let routes = routes.lock().unwrap();
+                        (routes.get(&format!("{method} {path}")))
+                            .or_else(|| routes.get(&path))
+                            .cloned()
+                    };
+                    let mut route = found.unwrap_or(Route {
+                        status: 404,
+                        ..Default::default()
+                    });
+                    tokio::time::sleep(route.delay).await;
+                    if let Some(etag) = route.etag.clone() {
+                        let matched = request.lines().any(|header| {
+                            header.split_once(':').is_some_and(|(k, v)| {
+                                k.eq_ignore_ascii_case("if-none-match") && v.trim() == etag
+                            })
+                        });
+                        route = match matched {
+                            true => Route {
+                                status: 304,
+                                ..Default::default()
+                            },
+                            false => {
+                                route.headers.push(("ETag".to_string(), etag));
+                                route
+                            }
+                        };
+                    }
+
+                    let mut response = format!("HTTP/1.1 {} Mock\r\n", route.status);
+                    for (k, v) in &route.headers {
+                        response.push_str(&format!("{k}: {v}\r\n"));
+                    }
+                    let body = if method == "HEAD" { "" } else { &route.body };
+                    response.push_str(&format!(
+                        "Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        route.body.len()
+                    ));
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        Self {
+            addr,
+            routes,
+            requests,
+        }
+    }
+
+    /// Serve the route for the given path from now on, replacing the previous one.
+    pub fn route(&self, path: &str, route: Route) {
+        let mut routes = self.routes.lock().unwrap();
+        routes.insert(path.to_string(), route);
+    }
+
+    /// Change the route served for the given path, if any.
+    pub fn update_route(&self, path: &str, f: impl FnOnce(&mut Route)) {
+        let mut routes = self.routes.lock().unwrap();
+        if let Some(route) = routes.get_mut(path) {
+            f(route);
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// URL pointing to this server with the given host name.
+    pub fn url(&self, host: &str, path: &str) -> String {
+        format!("http://{host}:{}{path}", self.addr.port())
+    }
+
+    /// A client resolving all the given hosts to this server.
+    #[cfg_attr(not(feature = "remote_rules"), allow(dead_code))]
+    pub fn client(&self, hosts: &[&str]) -> reqwest::Client {
+        hosts
+            .iter()
+            .fold(reqwest::Client::builder().no_proxy(), |builder, host| {
+                builder.resolve(host, self.addr)
+            })
+            .build()
+            .unwrap()
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
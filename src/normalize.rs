@@ -0,0 +1,354 @@
+use url::Url;
+
+/// Normalization describe the optional canonicalization steps applied to the cleaned URL.
+/// They are run as the final stage of `UrlCleaner::clear()`, after hooks. None of them
+/// are enabled by default.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Normalization {
+    /// Remove the leading `www.` label from the host.
+    pub strip_www: bool,
+    /// Remove the port when it is the default port of the scheme.
+    pub remove_default_port: bool,
+    /// Collapse consecutive slashes in path into a single one.
+    pub collapse_slashes: bool,
+    /// Remove the trailing slash of the path. The root path `/` is kept.
+    pub remove_trailing_slash: bool,
+    /// Lowercase the host.
+    pub lowercase_host: bool,
+    /// Remove the trailing dot of a fully qualified host, like `example.com.`. Rules apply
+    /// to both forms, but the output keeps the form of the input unless this is enabled.
+    pub strip_trailing_dot: bool,
+    /// Sort query pairs bytewise by their raw key. Pairs with the same key keep their original
+    /// relative order, so repeated keys like `a=2&a=1` keep their meaning.
+    pub sort_query: bool,
+    /// Remove exact duplicated `key=value` query pairs, keeping the first one.
+    pub dedup_params: bool,
+}
+
+impl Normalization {
+    /// Normalization with every step enabled.
+    pub fn all() -> Self {
+        Self {
+            strip_www: true,
+            remove_default_port: true,
+            collapse_slashes: true,
+            remove_trailing_slash: true,
+            lowercase_host: true,
+            strip_trailing_dot: true,
+            sort_query: true,
+            dedup_params: true,
+        }
+    }
+
+    /// Whether no step is enabled.
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Apply normalization to the given URL. Return true if the URL is modified.
+    pub fn apply(&self, url: &mut Url) -> bool {
+        let original = url.as_str().to_string();
+
+        if self.lowercase_host {
+            lowercase_host(url);
+        }
+        if self.strip_trailing_dot {
+            strip_trailing_dot(url);
+        }
+        if self.strip_www {
+            strip_www(url);
+        }
+        if self.remove_default_port {
+            remove_default_port(url);
+        }
+        if self.collapse_slashes {
+            collapse_slashes(url);
+        }
+        if self.remove_trailing_slash {
+            remove_trailing_slash(url);
+        }
+        if self.dedup_params {
+            dedup_params(url);
+        }
+        if self.sort_query {
+            sort_query(url);
+        }
+
+        url.as_str() != original
+    }
+}
+
+fn lowercase_host(url: &mut Url) {
+    let Some(host) = url.host_str() else {
+        return;
+    };
+    if !host.bytes().any(|b| b.is_ascii_uppercase()) {
+        return;
+    }
+
+    let host = host.to_ascii_lowercase();
+    // Lowercasing a valid host never makes it invalid
+    let _ = url.set_host(Some(&host));
+}
+
+fn strip_trailing_dot(url: &mut Url) {
+    let Some(stripped) = url.domain().and_then(|domain| domain.strip_suffix('.')) else {
+        return;
+    };
+
+    let stripped = stripped.to_string();
+    let _ = url.set_host(Some(&stripped));
+}
+
+fn strip_www(url: &mut Url) {
+    let Some(domain) = url.domain() else {
+        return;
+    };
+    let Some(stripped) = domain.strip_prefix("www.") else {
+        return;
+    };
+    // Keep `www.com` alike domain untouched
+    if !stripped.contains('.') {
+        return;
+    }
+
+    let stripped = stripped.to_string();
+    let _ = url.set_host(Some(&stripped));
+}
+
+fn remove_default_port(url: &mut Url) {
+    let default_port = match url.scheme() {
+        "http" | "ws" => 80,
+        "https" | "wss" => 443,
+        "ftp" => 21,
+        _ => return,
+    };
+
+    if url.port() == Some(default_port) {
+        let _ = url.set_port(None);
+    }
+}
+
+fn collapse_slashes(url: &mut Url) {
+    if url.cannot_be_a_base() || !url.path().contains("//") {
+        return;
+    }
+
+    let mut path = String::with_capacity(url.path().len());
+    for c in url.path().chars() {
+        if c == '/' && path.ends_with('/') {
+            continue;
+        }
+        path.push(c);
+    }
+    url.set_path(&path);
+}
+
+fn remove_trailing_slash(url: &mut Url) {
+    if url.cannot_be_a_base() {
+        return;
+    }
+
+    let path = url.path().trim_end_matches('/');
+    if path.is_empty() || path.len() == url.path().len() {
+        return;
+    }
+
+    let path = path.to_string();
+    url.set_path(&path);
+}
+
+fn sort_query(url: &mut Url) {
+    let Some(query) = url.query() else {
+        return;
+    };
+
+    let mut pairs: Vec<&str> = query.split('&').collect();
+    // `sort_by` is stable, so duplicate keys keep their order
+    pairs.sort_by(|a, b| query_key(a).cmp(query_key(b)));
+
+    let sorted = pairs.join("&");
+    if sorted != query {
+        url.set_query(Some(&sorted));
+    }
+}
+
+fn dedup_params(url: &mut Url) {
+    let Some(query) = url.query() else {
+        return;
+    };
+
+    let mut pairs: Vec<&str> = Vec::new();
+    for pair in query.split('&') {
+        if !pairs.contains(&pair) {
+            pairs.push(pair);
+        }
+    }
+
+    let deduped = pairs.join("&");
+    if deduped.len() != query.len() {
+        url.set_query(Some(&deduped));
+    }
+}
+
+fn query_key(pair: &str) -> &str {
+    pair.split_once('=').map_or(pair, |(k, _)| k)
+}
+
+#[cfg(test)]
+fn normalized(n: &Normalization, input: &str) -> (String, bool) {
+    let mut url = Url::parse(input).unwrap();
+    let changed = n.apply(&mut url);
+    (url.to_string(), changed)
+}
+
+#[test]
+fn test_normalization_flags() {
+    let strip_www = Normalization {
+        strip_www: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        normalized(&strip_www, "https://www.example.com/a"),
+        ("https://example.com/a".to_string(), true)
+    );
+    assert_eq!(
+        normalized(&strip_www, "https://www.com/a"),
+        ("https://www.com/a".to_string(), false)
+    );
+
+    // `url` already drop the default port of special scheme while parsing
+    let remove_default_port = Normalization {
+        remove_default_port: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        normalized(&remove_default_port, "https://example.com:443/a"),
+        ("https://example.com/a".to_string(), false)
+    );
+    assert_eq!(
+        normalized(&remove_default_port, "https://example.com:8443/a"),
+        ("https://example.com:8443/a".to_string(), false)
+    );
+
+    let collapse_slashes = Normalization {
+        collapse_slashes: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        normalized(&collapse_slashes, "https://example.com//path///to?x=1"),
+        ("https://example.com/path/to?x=1".to_string(), true)
+    );
+    assert_eq!(
+        normalized(&collapse_slashes, "http://host//"),
+        ("http://host/".to_string(), true)
+    );
+
+    let remove_trailing_slash = Normalization {
+        remove_trailing_slash: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        normalized(&remove_trailing_slash, "https://example.com/a/b/?x=1"),
+        ("https://example.com/a/b?x=1".to_string(), true)
+    );
+    assert_eq!(
+        normalized(&remove_trailing_slash, "https://example.com/"),
+        ("https://example.com/".to_string(), false)
+    );
+
+    let lowercase_host = Normalization {
+        lowercase_host: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        normalized(&lowercase_host, "foo://EXAMPLE.com/A"),
+        ("foo://example.com/A".to_string(), true)
+    );
+
+    let strip_trailing_dot = Normalization {
+        strip_trailing_dot: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        normalized(&strip_trailing_dot, "https://example.com./a"),
+        ("https://example.com/a".to_string(), true)
+    );
+    assert_eq!(
+        normalized(&strip_trailing_dot, "https://127.0.0.1/a"),
+        ("https://127.0.0.1/a".to_string(), false)
+    );
+
+    let sort_query = Normalization {
+        sort_query: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        normalized(&sort_query, "https://example.com/?b=1&a=2&b=0&a=1"),
+        ("https://example.com/?a=2&a=1&b=1&b=0".to_string(), true)
+    );
+    assert_eq!(
+        normalized(&sort_query, "https://example.com/?a=1&b=2"),
+        ("https://example.com/?a=1&b=2".to_string(), false)
+    );
+
+    let dedup_params = Normalization {
+        dedup_params: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        normalized(&dedup_params, "https://example.com/?a=1&b&a=2&a=1&b"),
+        ("https://example.com/?a=1&b&a=2".to_string(), true)
+    );
+    assert_eq!(
+        normalized(&dedup_params, "https://example.com/?a=1&a=2"),
+        ("https://example.com/?a=1&a=2".to_string(), false)
+    );
+}
+
+#[test]
+fn test_normalization_all() {
+    assert_eq!(
+        normalized(
+            &Normalization::all(),
+            "https://www.example.com:443//path//to/?x=1"
+        ),
+        ("https://example.com/path/to?x=1".to_string(), true)
+    );
+    assert_eq!(
+        normalized(&Normalization::all(), "http://host//"),
+        ("http://host/".to_string(), true)
+    );
+    assert_eq!(
+        normalized(&Normalization::default(), "https://www.example.com//a/"),
+        ("https://www.example.com//a/".to_string(), false)
+    );
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_sort_query_stable(
+        pairs in proptest::collection::vec(("[a-c]{1,2}", "[0-9]{0,2}"), 1..10)
+    ) {
+        let query: Vec<_> = pairs.iter().map(|(k, v)| format!("{k}={v}")).collect();
+        let sort_query = Normalization {
+            sort_query: true,
+            ..Default::default()
+        };
+        let input = format!("https://example.com/?{}", query.join("&"));
+        let (once, _) = normalized(&sort_query, &input);
+        let (twice, changed) = normalized(&sort_query, &once);
+        proptest::prop_assert_eq!(&once, &twice);
+        proptest::prop_assert!(!changed);
+
+        let url = Url::parse(&once).unwrap();
+        let sorted: Vec<&str> = url.query().unwrap().split('&').collect();
+        proptest::prop_assert!(sorted.windows(2).all(|w| query_key(w[0]) <= query_key(w[1])));
+        for (key, _) in &pairs {
+            let before: Vec<_> = query.iter().filter(|p| query_key(p) == key).collect();
+            let after: Vec<_> = sorted.iter().filter(|p| query_key(p) == key).collect();
+            proptest::prop_assert_eq!(before, after);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::Path;
+
+/// Topical packs of the bundled ruleset, selected with
+/// [`UrlCleaner::with_presets`](crate::UrlCleaner::with_presets). The default rule is always
+/// included.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Preset {
+    /// Social networks, video and music platforms and their short links, like Twitter,
+    /// bilibili, YouTube, Instagram and TikTok
+    Social,
+    /// Online shops, like Amazon, AliExpress, eBay and Taobao
+    Ecommerce,
+    /// The whole bundled ruleset
+    Everything,
+}
+
+/// A TOML document of the bundled ruleset.
+struct Pack {
+    path: &'static str,
+    content: &'static str,
+}
+
+const DEFAULT: Pack = Pack {
+    path: "rules/default.toml",
+    content: include_str!("../rules/default.toml"),
+};
+const SOCIAL: Pack = Pack {
+    path: "rules/social.toml",
+    content: include_str!("../rules/social.toml"),
+};
+const ECOMMERCE: Pack = Pack {
+    path: "rules/ecommerce.toml",
+    content: include_str!("../rules/ecommerce.toml"),
+};
+const MISC: Pack = Pack {
+    path: "rules/misc.toml",
+    content: include_str!("../rules/misc.toml"),
+};
+
+impl Preset {
+    fn packs(self) -> &'static [Pack] {
+        match self {
+            Preset::Social => &[SOCIAL],
+            Preset::Ecommerce => &[ECOMMERCE],
+            Preset::Everything => &[SOCIAL, ECOMMERCE, MISC],
+        }
+    }
+}
+
+/// The documents to merge for the given presets: the default rule first, then each selected
+/// pack once, in the order of the bundled ruleset.
+pub(crate) fn documents(presets: &[Preset]) -> Vec<(&'static str, Option<&'static Path>)> {
+    let mut documents = vec![(DEFAULT.content, Some(Path::new(DEFAULT.path)))];
+    for pack in [SOCIAL, ECOMMERCE, MISC] {
+        let selected = presets
+            .iter()
+            .any(|preset| preset.packs().iter().any(|p| p.path == pack.path));
+        if selected {
+            documents.push((pack.content, Some(Path::new(pack.path))));
+        }
+    }
+    documents
+}
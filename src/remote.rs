@@ -0,0 +1,401 @@
+//! A base ruleset fetched over HTTP, with the local rules layered over it. See
+//! [`UrlCleanerBuilder::remote_rules`](crate::UrlCleanerBuilder::remote_rules).
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use reqwest::{header, StatusCode};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::event::{CleanEvent, Observer};
+use crate::rules::{self, ParseOptions, RuleParseError, RuleWarning, Rules, RulesetDiff};
+
+/// How the remote base ruleset is refreshed, cached and verified.
+#[derive(Clone, Debug)]
+pub struct RemotePolicy {
+    /// Interval between two refreshes in the background. Zero disables refreshing, the
+    /// ruleset is only fetched when the cleaner is built.
+    pub refresh: Duration,
+    /// Where the last good copy of the remote ruleset is kept, to start without network or
+    /// when the remote copy is broken. Its ETag is kept next to it, in `<etag_cache>.etag`.
+    pub etag_cache: PathBuf,
+    /// The hex sha256 digest the remote ruleset must have. A copy with any other digest is
+    /// rejected, the cached one included.
+    pub verify_sha256: Option<String>,
+}
+
+/// The remote ruleset and the local documents merged over it.
+pub(crate) struct Remote {
+    pub url: Url,
+    pub policy: RemotePolicy,
+    pub client: reqwest::Client,
+    pub local: Vec<(String, Option<PathBuf>)>,
+    pub parse_options: ParseOptions,
+    pub observer: Option<Observer>,
+}
+
+/// The remote ruleset as last fetched or cached.
+struct Copy {
+    body: String,
+    etag: Option<String>,
+}
+
+enum Fetched {
+    NotModified,
+    Modified(Copy),
+}
+
+impl Remote {
+    /// Fetch the remote ruleset, falling back to the cached copy when it can't be fetched
+    /// or is invalid. Return the merged rules and the ETag of the copy used.
+    pub async fn load(&self) -> Result<(Rules, Vec<RuleWarning>, Option<String>), RuleParseError> {
+        let cached = self.read_cache();
+        let etag = cached.as_ref().and_then(|copy| copy.etag.as_deref());
+        let error = match self.fetch(etag).await {
+            Ok(Fetched::Modified(copy)) => match self.accept(&copy.body) {
+                Ok((rules, warnings)) => {
+                    self.write_cache(&copy);
+                    return Ok((rules, warnings, copy.etag));
+                }
+                Err(err) => err,
+            },
+            Ok(Fetched::NotModified) => self.fetch_error("not modified, but nothing is cached"),
+            Err(err) => err,
+        };
+
+        match cached {
+            Some(copy) => {
+                let (rules, warnings) = self.accept(&copy.body)?;
+                Ok((rules, warnings, copy.etag))
+            }
+            None => Err(error),
+        }
+    }
+
+    /// Refresh the rules every [`RemotePolicy::refresh`], as long as the cleaner is alive.
+    pub fn spawn_refresh(self, target: Weak<ArcSwap<Rules>>, etag: Option<String>) {
+        if self.policy.refresh.is_zero() {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut etag = etag;
+            loop {
+                tokio::time::sleep(self.policy.refresh).await;
+                let Some(target) = target.upgrade() else {
+                    return;
+                };
+                self.refresh_into(&target, &mut etag).await;
+            }
+        });
+    }
+
+    /// Replace the rules if the remote ruleset changed and is valid, and tell the observer.
+    /// The current rules are kept when it is unchanged or invalid.
+    async fn refresh_into(&self, target: &ArcSwap<Rules>, etag: &mut Option<String>) {
+        let event = match self.refresh(etag).await {
+            Ok(None) => return,
+            Ok(Some(rules)) => {
+                let diff = RulesetDiff::between(&target.load(), &rules);
+                target.store(Arc::new(rules));
+                CleanEvent::RulesRefreshed { diff }
+            }
+            Err(err) => CleanEvent::RulesRefreshFailed {
+                message: err.to_string(),
+            },
+        };
+        if let Some(observer) = &self.observer {
+            observer(event);
+        }
+    }
+
+    /// Fetch the remote ruleset once more. Return the new rules if it changed.
+    async fn refresh(&self, etag: &mut Option<String>) -> Result<Option<Rules>, RuleParseError> {
+        let Fetched::Modified(copy) = self.fetch(etag.as_deref()).await? else {
+            return Ok(None);
+        };
+        let (rules, _) = self.accept(&copy.body)?;
+        self.write_cache(&copy);
+        *etag = copy.etag;
+        Ok(Some(rules))
+    }
+
+    async fn fetch(&self, etag: Option<&str>) -> Result<Fetched, RuleParseError> {
+        let mut request = self.client.get(self.url.clone());
+        if let Some(etag) = etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|err| self.fetch_error(&err.to_string()))?;
+        match response.status() {
+            StatusCode::NOT_MODIFIED => return Ok(Fetched::NotModified),
+            status if !status.is_success() => {
+                return Err(self.fetch_error(&format!("server responded {status}")))
+            }
+            _ => {}
+        }
+
+        let etag = response
+            .headers()
+            .get(header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_string);
+        let body = response
+            .text()
+            .await
+            .map_err(|err| self.fetch_error(&err.to_string()))?;
+        Ok(Fetched::Modified(Copy { body, etag }))
+    }
+
+    /// Verify the remote ruleset against the pin, then merge the local rules over it.
+    fn accept(&self, body: &str) -> Result<(Rules, Vec<RuleWarning>), RuleParseError> {
+        if let Some(expected) = &self.policy.verify_sha256 {
+            let actual = hex(&Sha256::digest(body.as_bytes()));
+            if !actual.eq_ignore_ascii_case(expected.trim()) {
+                return Err(RuleParseError::Sha256Mismatch {
+                    url: self.url.to_string(),
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+
+        let mut documents = vec![(body, Some(Path::new(self.url.as_str())))];
+        documents.extend(
+            self.local
+                .iter()
+                .map(|(content, file)| (content.as_str(), file.as_deref())),
+        );
+        rules::parse_documents(&documents, &self.parse_options)
+    }
+
+    fn read_cache(&self) -> Option<Copy> {
+        let body = std::fs::read_to_string(&self.policy.etag_cache).ok()?;
+        let etag = std::fs::read_to_string(self.etag_path())
+            .ok()
+            .map(|etag| etag.trim().to_string())
+            .filter(|etag| !etag.is_empty());
+        Some(Copy { body, etag })
+    }
+
+    /// Keep the copy for the next start. The cache is an optimization, failing to write it
+    /// doesn't fail the refresh.
+    fn write_cache(&self, copy: &Copy) {
+        let path = &self.policy.etag_cache;
+        let mut partial = path.clone().into_os_string();
+        partial.push(".partial");
+        // written aside then renamed, so a crash never leaves a truncated cache
+        if std::fs::write(&partial, &copy.body).is_ok() {
+            let _ = std::fs::rename(&partial, path);
+        }
+        let _ = match &copy.etag {
+            Some(etag) => std::fs::write(self.etag_path(), etag),
+            None => std::fs::remove_file(self.etag_path()),
+        };
+    }
+
+    fn etag_path(&self) -> PathBuf {
+        let mut path = self.policy.etag_cache.clone().into_os_string();
+        path.push(".etag");
+        path.into()
+    }
+
+    fn fetch_error(&self, message: &str) -> RuleParseError {
+        RuleParseError::Fetch {
+            url: self.url.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+const BASE: &str = r#"
+["example.com"]
+ban = ["utm_source"]
+
+["shop.test"]
+ban = ["spm"]
+"#;
+
+#[cfg(test)]
+const LOCAL: &str = r#"
+["shop.test"]
+ban = ["spm", "ref"]
+"#;
+
+/// A remote loader for the given mock server, caching in a fresh directory.
+#[cfg(test)]
+fn test_remote(
+    server: &crate::mock::MockServer,
+    name: &str,
+    pin: Option<String>,
+) -> (Remote, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("clearurl-remote-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let remote = Remote {
+        url: Url::parse(&server.url("rules.test", "/rules.toml")).unwrap(),
+        policy: RemotePolicy {
+            refresh: Duration::ZERO,
+            etag_cache: dir.join("rules.toml"),
+            verify_sha256: pin,
+        },
+        client: server.client(&["rules.test"]),
+        local: vec![(LOCAL.to_string(), None)],
+        parse_options: ParseOptions::default(),
+        observer: None,
+    };
+    (remote, dir)
+}
+
+#[tokio::test]
+async fn test_remote_fetch_and_refresh() {
+    use crate::mock::{MockServer, Route};
+
+    let server = MockServer::start(vec![("/rules.toml", Route::ok(BASE).etag("\"v1\""))]).await;
+    let (remote, dir) = test_remote(&server, "fetch", None);
+
+    let (rules, _, etag) = remote.load().await.unwrap();
+    assert_eq!(etag.as_deref(), Some("\"v1\""));
+    assert!(rules.hosts.contains_key("example.com"));
+    // the local entry overwrites the remote one
+    assert_eq!(rules.hosts["shop.test"].rules.len(), 2);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("rules.toml")).unwrap(),
+        BASE
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("rules.toml.etag")).unwrap(),
+        "\"v1\""
+    );
+
+    // unchanged: the server answers 304 and the current rules are kept
+    let mut etag = etag;
+    assert!(remote.refresh(&mut etag).await.unwrap().is_none());
+    assert_eq!(etag.as_deref(), Some("\"v1\""));
+
+    // stale ETag: the ruleset is fetched again
+    let mut stale = Some("\"v0\"".to_string());
+    assert!(remote.refresh(&mut stale).await.unwrap().is_some());
+    assert_eq!(stale.as_deref(), Some("\"v1\""));
+
+    // starting again sends the cached ETag and uses the cached copy
+    let (rules, _, _) = remote.load().await.unwrap();
+    assert!(rules.hosts.contains_key("example.com"));
+    assert_eq!(server.requests().len(), 4);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_remote_sha256_pin() {
+    use crate::mock::{MockServer, Route};
+
+    let pin = hex(&Sha256::digest(BASE.as_bytes()));
+    let tampered = BASE.replace("utm_source", "utm_medium");
+    let server = MockServer::start(vec![("/rules.toml", Route::ok(&tampered))]).await;
+
+    let (remote, dir) = test_remote(&server, "pin", Some(pin.clone()));
+    match remote.load().await {
+        Err(RuleParseError::Sha256Mismatch { expected, .. }) => assert_eq!(expected, pin),
+        Err(err) => panic!("expect sha256 mismatch, got {err}"),
+        Ok(_) => panic!("tampered rules are accepted"),
+    }
+    assert!(!dir.join("rules.toml").exists());
+
+    // the last good copy is used instead of the tampered one, and kept
+    std::fs::write(dir.join("rules.toml"), BASE).unwrap();
+    let (rules, _, _) = remote.load().await.unwrap();
+    assert!(rules.hosts["example.com"].rules[0].is_match("utm_source"));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("rules.toml")).unwrap(),
+        BASE
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_remote_offline_startup() {
+    use crate::mock::MockServer;
+
+    let server = MockServer::start(vec![]).await;
+    let (mut remote, dir) = test_remote(&server, "offline", None);
+    // nothing listens on the discard port
+    remote.url = Url::parse("http://127.0.0.1:9/rules.toml").unwrap();
+    remote.client = reqwest::Client::builder().no_proxy().build().unwrap();
+
+    assert!(matches!(
+        remote.load().await,
+        Err(RuleParseError::Fetch { .. })
+    ));
+
+    std::fs::write(dir.join("rules.toml"), BASE).unwrap();
+    let (rules, _, etag) = remote.load().await.unwrap();
+    assert!(rules.hosts.contains_key("example.com"));
+    assert_eq!(rules.hosts["shop.test"].rules.len(), 2);
+    assert_eq!(etag, None);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_remote_refresh_events() {
+    use crate::mock::{MockServer, Route};
+
+    let updated = format!("{BASE}\n[\"new.test\"]\nban = [\"a\", \"b\"]\n");
+    let server = MockServer::start(vec![
+        ("/rules.toml", Route::ok(&updated).etag("\"v2\"")),
+        ("/broken.toml", Route::ok("[\"new.test\"\nban = 1")),
+    ])
+    .await;
+    let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let collector = Arc::clone(&events);
+    let observer: Observer = Arc::new(move |event| collector.lock().unwrap().push(event));
+
+    let (mut remote, dir) = test_remote(&server, "events", None);
+    remote.observer = Some(Arc::clone(&observer));
+    let (rules, _) = remote.accept(BASE).unwrap();
+    let target = ArcSwap::from_pointee(rules);
+
+    let mut etag = Some("\"v1\"".to_string());
+    remote.refresh_into(&target, &mut etag).await;
+    assert!(target.load().hosts.contains_key("new.test"));
+    assert_eq!(etag.as_deref(), Some("\"v2\""));
+
+    // unchanged, nothing to tell
+    remote.refresh_into(&target, &mut etag).await;
+
+    let mut broken = Remote {
+        url: Url::parse(&server.url("rules.test", "/broken.toml")).unwrap(),
+        ..remote
+    };
+    broken.policy.etag_cache = dir.join("broken.toml");
+    broken.refresh_into(&target, &mut None).await;
+    // the old rules are kept
+    assert!(target.load().hosts.contains_key("new.test"));
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(
+        events[0],
+        CleanEvent::RulesRefreshed {
+            diff: RulesetDiff {
+                domains_added: 1,
+                patterns_added: 2,
+                ..Default::default()
+            }
+        }
+    );
+    assert!(
+        matches!(&events[1], CleanEvent::RulesRefreshFailed { message } if message.contains("broken.toml"))
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
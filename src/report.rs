@@ -0,0 +1,309 @@
+use std::time::Duration;
+
+use url::Url;
+
+use crate::components::ComponentMask;
+use crate::event::{CleanEvent, Observer};
+use crate::rules::Rules;
+use crate::suspicious::SuspiciousParam;
+
+/// ClearReport describe what happened while cleaning a URL. It is returned by
+/// [`UrlCleaner::explain`](crate::UrlCleaner::explain) alongside the result.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClearReport {
+    /// The domain of the rule that matched, the regex of a URL pattern rule, `default` for
+    /// the fallback rule, or `None` when no rule applies.
+    pub matched_rule: Option<String>,
+    /// The URL after following redirection, if any.
+    pub redirected_to: Option<Url>,
+    /// How long resolving the redirection took, if any.
+    pub redirect_elapsed: Option<Duration>,
+    /// Every shortener resolved on the way to `redirected_to`, in order.
+    pub redirect_chain: Vec<RedirectHop>,
+    /// Keys of the removed query parameters, in query order.
+    pub removed_params: Vec<String>,
+    /// Values of the removed parameters, one for each of `removed_params`, as selected with
+    /// [`UrlCleanerBuilder::report_detail`](crate::UrlCleanerBuilder::report_detail).
+    /// Empty with [`ReportDetail::KeysOnly`], the default.
+    pub removed_values: Vec<RemovedValue>,
+    /// Keys of the parameters removed because their value embeds a URL of a denied host.
+    /// They are also listed in `removed_params`.
+    pub denied_values: Vec<String>,
+    /// Keys of the query parameters in the result that the URL didn't have, added by hooks,
+    /// in query order. Hooks run after the query filter, so these are never removed again
+    /// by the ban list of the rule.
+    pub added_params: Vec<String>,
+    /// Names of the post hooks applied, in execution order.
+    pub hooks_applied: Vec<String>,
+    /// Names of the post hooks skipped because they would change a component not selected
+    /// by the [`ComponentMask`](crate::ComponentMask).
+    pub hooks_skipped: Vec<String>,
+    /// Whether the host of the result differs from the host the rule is applied to, by hooks
+    /// or normalization. Redirection alone doesn't count.
+    pub host_rewritten: bool,
+    /// Whether the query has more parameters than `Limits::max_params`, so the ones beyond
+    /// the limit are kept without being examined.
+    pub params_truncated: bool,
+    /// Whether cleaning would leave only the origin, and the `if_reduced_to_origin` policy
+    /// kept the original URL or failed instead.
+    pub origin_policy_applied: bool,
+    /// Length in bytes of the query before cleaning, after redirection, as written in the
+    /// URL. 0 without a query.
+    pub original_query_len: usize,
+    /// Length in bytes of the query of the result.
+    pub cleaned_query_len: usize,
+    /// How many bytes shorter the result is than the URL it is cleaned from, the query and
+    /// every other component together.
+    pub bytes_removed: usize,
+    /// Number of parameters in the query before cleaning.
+    pub params_total: usize,
+    /// Number of those parameters that are not in the query of the result. Parameters
+    /// added by hooks are not counted against the removed ones.
+    pub params_removed_count: usize,
+    /// Parameters kept in the result that look like tracking, when enabled with
+    /// [`UrlCleanerBuilder::flag_suspicious_params`](crate::UrlCleanerBuilder::flag_suspicious_params).
+    pub suspicious: Vec<SuspiciousParam>,
+}
+
+/// One shortener resolved while following a redirection, see
+/// [`ClearReport::redirect_chain`]. HTTP redirections followed by the client on their own,
+/// within the same resolution, are not listed apart.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RedirectHop {
+    /// The URL requested.
+    pub url: Url,
+    /// The status of the last response of the resolution.
+    pub status: u16,
+    pub elapsed: Duration,
+    /// Where the HEAD request ended when it differs from where the GET request ended, with
+    /// [`UrlCleanerBuilder::detect_cloaking`](crate::UrlCleanerBuilder::detect_cloaking).
+    /// The target of the GET request is the one followed.
+    pub head_target: Option<Url>,
+}
+
+/// How much of the removed parameters is reported, in [`ClearReport::removed_values`] and
+/// the [`CleanEvent`]s. Set with
+/// [`UrlCleanerBuilder::report_detail`](crate::UrlCleanerBuilder::report_detail).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReportDetail {
+    /// Only the keys. Values are never copied.
+    #[default]
+    KeysOnly,
+    /// The length and a hash of the values, to tell them apart without revealing them.
+    Redacted,
+    /// The values as they are written in the URL, still percent-encoded.
+    Full,
+}
+
+/// The value of a removed parameter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RemovedValue {
+    /// The length in bytes and the first 8 hex digits of the 64-bit FNV-1a hash of the
+    /// value.
+    Redacted {
+        len: usize,
+        hash: String,
+    },
+    Full(String),
+}
+
+impl RemovedValue {
+    /// The value of the `key=value` pair at the given detail, `None` for
+    /// [`ReportDetail::KeysOnly`]. A pair without `=` has an empty value.
+    pub(crate) fn of(pair: &str, detail: ReportDetail) -> Option<Self> {
+        let value = pair.split_once('=').map_or("", |(_, value)| value);
+        match detail {
+            ReportDetail::KeysOnly => None,
+            ReportDetail::Redacted => {
+                let hash = value.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+                    (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+                });
+                Some(Self::Redacted {
+                    len: value.len(),
+                    hash: format!("{:08x}", hash >> 32),
+                })
+            }
+            ReportDetail::Full => Some(Self::Full(value.to_string())),
+        }
+    }
+}
+
+/// What [`UrlCleaner::clean_in_place`](crate::UrlCleaner::clean_in_place) changed, so the
+/// caller can tell whether the URL needs to be written back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CleanDelta {
+    /// Number of query and matrix parameters removed.
+    pub params_removed: usize,
+    /// Whether the host is changed by hooks or normalization.
+    pub host_changed: bool,
+    /// Whether the fragment is dropped.
+    pub fragment_cleared: bool,
+}
+
+impl ClearReport {
+    /// Whether any query parameter is removed.
+    pub fn params_removed(&self) -> bool {
+        !self.removed_params.is_empty()
+    }
+
+    /// Whether a redirection is followed.
+    pub fn redirect_followed(&self) -> bool {
+        self.redirected_to.is_some()
+    }
+
+    /// Whether a shortener sent HEAD and GET requests to different targets.
+    pub fn cloaking_detected(&self) -> bool {
+        (self.redirect_chain.iter()).any(|hop| hop.head_target.is_some())
+    }
+
+    /// Share of the query parameters that are removed, between 0 and 1. 0 without a query.
+    pub fn tracking_ratio(&self) -> f64 {
+        match self.params_total {
+            0 => 0.0,
+            total => self.params_removed_count as f64 / total as f64,
+        }
+    }
+
+    /// Fill the size fields and the added parameters from the URL a rule is applied to and
+    /// the result. Pairs are compared as written, so a value changed by a hook counts as
+    /// removed and added.
+    pub(crate) fn record_sizes(&mut self, before: &Url, after: &Url) {
+        let pairs = |url: &Url| -> Vec<String> {
+            url.query().map_or(Vec::new(), |query| {
+                (query.split('&').filter(|p| !p.is_empty()))
+                    .map(str::to_string)
+                    .collect()
+            })
+        };
+        let (before_pairs, mut added) = (pairs(before), pairs(after));
+        // each pair of the result is matched with at most one of the original
+        let mut removed = 0;
+        for pair in &before_pairs {
+            match added.iter().position(|kept| kept == pair) {
+                Some(i) => {
+                    added.remove(i);
+                }
+                None => removed += 1,
+            }
+        }
+        self.added_params = (added.iter())
+            .map(|pair| crate::query_key(pair).into_owned())
+            .collect();
+        self.original_query_len = before.query().map_or(0, str::len);
+        self.cleaned_query_len = after.query().map_or(0, str::len);
+        self.bytes_removed = before.as_str().len().saturating_sub(after.as_str().len());
+        self.params_total = before_pairs.len();
+        self.params_removed_count = removed;
+    }
+
+    fn record(&mut self, event: &CleanEvent) {
+        match event {
+            CleanEvent::ParamRemoved { key, value, .. } => {
+                self.removed_params.push(key.clone());
+                self.removed_values.extend(value.clone());
+            }
+            CleanEvent::ParamValueDenied { key, value, .. } => {
+                self.removed_params.push(key.clone());
+                self.removed_values.extend(value.clone());
+                self.denied_values.push(key.clone());
+            }
+            CleanEvent::HookApplied { name } => self.hooks_applied.push(name.clone()),
+            CleanEvent::HookSkipped { name } => self.hooks_skipped.push(name.clone()),
+            CleanEvent::RedirectFollowed { elapsed, .. } => self.redirect_elapsed = Some(*elapsed),
+            CleanEvent::RuleMissed { .. } => {}
+            #[cfg(feature = "remote_rules")]
+            CleanEvent::RulesRefreshed { .. } | CleanEvent::RulesRefreshFailed { .. } => {}
+        }
+    }
+}
+
+/// Trace carry the stages to run, the optional observer and report through one cleaning,
+/// so nothing is allocated when neither of the latter is requested.
+pub(crate) struct Trace<'a> {
+    mask: ComponentMask,
+    /// Rules consulted before the ones of the cleaner
+    overlay: Option<&'a Rules>,
+    observer: Option<&'a Observer>,
+    report: Option<&'a mut ClearReport>,
+    /// Number of parameters removed so far
+    removed: usize,
+}
+
+impl<'a> Trace<'a> {
+    pub(crate) fn new(observer: Option<&'a Observer>, report: Option<&'a mut ClearReport>) -> Self {
+        Self {
+            mask: ComponentMask::ALL,
+            overlay: None,
+            observer,
+            report,
+            removed: 0,
+        }
+    }
+
+    pub(crate) fn with_mask(mut self, mask: ComponentMask) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    #[inline]
+    pub(crate) fn mask(&self) -> ComponentMask {
+        self.mask
+    }
+
+    pub(crate) fn with_overlay(mut self, overlay: &'a Rules) -> Self {
+        self.overlay = Some(overlay);
+        self
+    }
+
+    #[inline]
+    pub(crate) fn overlay(&self) -> Option<&'a Rules> {
+        self.overlay
+    }
+
+    /// Whether the hook output only changes selected components, reporting the hook as
+    /// skipped otherwise.
+    #[cfg(feature = "hooks-core")]
+    pub(crate) fn allows_hook(&mut self, name: &str, before: &Url, after: &Url) -> bool {
+        if self.mask.allows(before, after) {
+            return true;
+        }
+        self.emit(|| CleanEvent::HookSkipped {
+            name: name.to_string(),
+        });
+        false
+    }
+
+    #[inline]
+    pub(crate) fn emit(&mut self, event: impl FnOnce() -> CleanEvent) {
+        if self.observer.is_none() && self.report.is_none() {
+            return;
+        }
+
+        let event = event();
+        if let Some(report) = self.report.as_deref_mut() {
+            report.record(&event);
+        }
+        if let Some(observer) = self.observer {
+            observer(event);
+        }
+    }
+
+    /// Count a removed parameter and emit its event.
+    #[inline]
+    pub(crate) fn param_removed(&mut self, event: impl FnOnce() -> CleanEvent) {
+        self.removed += 1;
+        self.emit(event);
+    }
+
+    #[inline]
+    pub(crate) fn removed(&self) -> usize {
+        self.removed
+    }
+
+    #[inline]
+    pub(crate) fn report(&mut self, f: impl FnOnce(&mut ClearReport)) {
+        if let Some(report) = self.report.as_deref_mut() {
+            f(report);
+        }
+    }
+}
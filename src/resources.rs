@@ -0,0 +1,32 @@
+//! What the state accumulated by a long-running cleaner holds.
+
+use std::fmt;
+
+/// Snapshot of the internal state a cleaner accumulates while running, returned by
+/// [`UrlCleaner::resources`](crate::UrlCleaner::resources). Byte counts are approximate,
+/// they cover the heap allocations of each map.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CleanerResources {
+    /// Hosts cooling down after asking to retry later, bounded by
+    /// `UrlCleanerBuilder::cooldown_capacity`. Always 0 without the `http` feature.
+    pub cooldown_hosts: usize,
+    pub cooldown_bytes: usize,
+}
+
+impl CleanerResources {
+    /// Approximate bytes used by all the maps.
+    pub fn total_bytes(&self) -> usize {
+        self.cooldown_bytes
+    }
+}
+
+/// One line like `cooldowns: 2 hosts (~184 B)`.
+impl fmt::Display for CleanerResources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cooldowns: {} hosts (~{} B)",
+            self.cooldown_hosts, self.cooldown_bytes
+        )
+    }
+}
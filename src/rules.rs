@@ -1,74 +1,1814 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use toml::Spanned;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Deserialize, Debug)]
 struct ConfigData {
     #[serde(default)]
-    sub: Option<Vec<String>>,
+    sub: Option<Vec<Spanned<String>>>,
+    #[serde(default)]
+    include_bare: Option<bool>,
     #[serde(default)]
     redirect: bool,
     #[serde(default)]
-    ban: Vec<String>,
+    redirect_path: Option<Spanned<String>>,
+    #[serde(default)]
+    redirect_mode: Option<RedirectMode>,
+    #[serde(default)]
+    ban: Vec<Spanned<BanEntry>>,
+    #[serde(default)]
+    post_hooks: Option<Vec<Spanned<HookEntry>>>,
+    #[serde(default)]
+    drop_all_query: bool,
+    #[serde(default)]
+    drop_fragment: bool,
+    #[serde(default)]
+    clean_fragment: bool,
+    #[serde(default)]
+    if_reduced_to_origin: Option<OriginPolicy>,
     #[serde(default)]
-    post_hooks: Option<Vec<String>>,
+    keep: Vec<Spanned<String>>,
+    #[serde(default)]
+    include_default: Option<bool>,
+    #[serde(default)]
+    case_insensitive: bool,
+    #[serde(default)]
+    ban_matrix: Vec<Spanned<String>>,
+    #[serde(default)]
+    tracker_allow: Option<Vec<String>>,
+    #[serde(default)]
+    tracker_deny: Option<Vec<String>>,
 }
 
-/// Represent rule for a single domain.
+/// The `[settings]` table applying to the whole ruleset.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    /// Apply the ban list of the default rule in addition to every domain rule
+    #[serde(default)]
+    default_is_global: bool,
+}
+
+/// How the redirection of a URL is resolved.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedirectMode {
+    /// Follow the HTTP redirection
+    #[default]
+    Http,
+    /// Load the page and read the target from its meta refresh, canonical link or `og:url`.
+    /// Used by shorteners serving an interstitial page.
+    Html,
+}
+
+/// What to do when cleaning leaves only the origin, like `https://example.com/`, of a URL
+/// that had a path or a query. Set per rule with `if_reduced_to_origin`, or for every rule
+/// with `UrlCleanerBuilder::if_reduced_to_origin`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OriginPolicy {
+    /// Keep the URL as it was before cleaning. It is reported as not cleaned, with
+    /// `UrlCleanError::ReducedToOrigin` as the reason: `clear*` return this error, only
+    /// `try_clear*` yield the URL as `ClearOutcome::AlreadyClean`.
+    KeepOriginal,
+    /// Return the origin.
+    #[default]
+    EmitOrigin,
+    /// Fail with `UrlCleanError::ReducedToOrigin`.
+    Error,
+}
+
+/// An entry in the `ban` list: either a pattern, or a group of patterns that only apply
+/// when some other keys present in the same query.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+enum BanEntry {
+    Pattern(String),
+    Conditional {
+        keys: Vec<String>,
+        when_present: Vec<String>,
+    },
+}
+
+/// An entry in the `post_hooks` list: either a hook name, or a table with the name and
+/// the arguments passed to the hook.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum HookEntry {
+    Name(String),
+    WithArgs {
+        name: String,
+        #[serde(default)]
+        args: HashMap<String, String>,
+    },
+}
+
+/// A post hook referenced by a rule, with its arguments.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HookCall {
+    pub name: String,
+    pub args: HashMap<String, String>,
+}
+
+impl From<HookEntry> for HookCall {
+    fn from(entry: HookEntry) -> Self {
+        match entry {
+            HookEntry::Name(name) => Self {
+                name,
+                args: HashMap::new(),
+            },
+            HookEntry::WithArgs { name, args } => Self { name, args },
+        }
+    }
+}
+
+/// Ban patterns that are applied only if any of the `when_present` keys exist in the query.
 #[derive(Clone, Debug)]
+pub struct ConditionalBan {
+    pub keys: Vec<Arc<regex::Regex>>,
+    /// Exact query keys that trigger this group
+    pub when_present: Vec<String>,
+}
+
+/// Counters of the ban patterns of a rule. Cloning snapshots the current counts.
+#[cfg(feature = "usage_stats")]
+#[derive(Debug, Default)]
+pub struct Hits(Vec<std::sync::atomic::AtomicU64>);
+
+#[cfg(feature = "usage_stats")]
+impl Hits {
+    pub fn new(len: usize) -> Self {
+        Self((0..len).map(|_| Default::default()).collect())
+    }
+
+    /// Add counters for new patterns, keeping the existing counts.
+    pub fn resize(&mut self, len: usize) {
+        self.0.resize_with(len, Default::default);
+    }
+
+    pub fn record(&self, index: usize) {
+        if let Some(hits) = self.0.get(index) {
+            hits.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    pub fn get(&self, index: usize) -> u64 {
+        self.0
+            .get(index)
+            .map_or(0, |hits| hits.load(std::sync::atomic::Ordering::Relaxed))
+    }
+}
+
+#[cfg(feature = "usage_stats")]
+impl Clone for Hits {
+    fn clone(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|hits| hits.load(std::sync::atomic::Ordering::Relaxed).into())
+                .collect(),
+        )
+    }
+}
+
+/// How many keys a ban pattern removed since the rules were loaded.
+#[cfg(feature = "usage_stats")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleUsage {
+    /// The table name of the rule in the ruleset
+    pub domain: String,
+    /// The ban pattern as written in the ruleset
+    pub pattern: String,
+    pub hits: u64,
+}
+
+/// How a ruleset differs from an older one, see
+/// [`UrlCleaner::diff_rules`](crate::UrlCleaner::diff_rules). Domains are the hosts with a
+/// rule, pattern rules counted by their regex, and patterns are the ban patterns of each of
+/// them. Displayed like `+3 domains, +12 patterns`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RulesetDiff {
+    pub domains_added: usize,
+    pub domains_removed: usize,
+    pub patterns_added: usize,
+    pub patterns_removed: usize,
+}
+
+impl RulesetDiff {
+    pub(crate) fn between(old: &Rules, new: &Rules) -> Self {
+        let mut diff = Self::default();
+        let (old, new) = (old.entries(), new.entries());
+        for (key, rule) in &new {
+            match old.get(key) {
+                Some(before) => {
+                    diff.patterns_added += count_missing(&rule.rules, &before.rules);
+                    diff.patterns_removed += count_missing(&before.rules, &rule.rules);
+                }
+                None => {
+                    diff.domains_added += 1;
+                    diff.patterns_added += rule.rules.len();
+                }
+            }
+        }
+        for (key, rule) in &old {
+            if !new.contains_key(key) {
+                diff.domains_removed += 1;
+                diff.patterns_removed += rule.rules.len();
+            }
+        }
+        diff
+    }
+
+    /// Whether the rulesets have the same domains and patterns.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Number of patterns of `of` not in `among`.
+fn count_missing(of: &[Arc<regex::Regex>], among: &[Arc<regex::Regex>]) -> usize {
+    of.iter()
+        .filter(|re| !among.iter().any(|other| other.as_str() == re.as_str()))
+        .count()
+}
+
+impl std::fmt::Display for RulesetDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let counts = [
+            ('+', self.domains_added, "domain"),
+            ('-', self.domains_removed, "domain"),
+            ('+', self.patterns_added, "pattern"),
+            ('-', self.patterns_removed, "pattern"),
+        ];
+        let parts: Vec<String> = counts
+            .iter()
+            .filter(|(_, count, _)| *count > 0)
+            .map(|(sign, count, noun)| match count {
+                1 => format!("{sign}1 {noun}"),
+                _ => format!("{sign}{count} {noun}s"),
+            })
+            .collect();
+        match parts.is_empty() {
+            true => write!(f, "no change"),
+            false => write!(f, "{}", parts.join(", ")),
+        }
+    }
+}
+
+/// Which `tr` trackers of a magnet link are kept, by host. With an allow list, trackers
+/// outside of it are removed; otherwise trackers in the deny list are removed.
+#[derive(Clone, Debug, Default)]
+pub struct TrackerFilter {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl TrackerFilter {
+    /// Whether the raw `key=value` pair is a tracker to remove.
+    pub fn rejects(&self, pair: &str) -> bool {
+        let Some(("tr", value)) = pair.split_once('=') else {
+            return false;
+        };
+        let tracker = url::Url::parse(&crate::text::percent_decode(value)).ok();
+        let Some(host) = tracker.as_ref().and_then(|url| url.host_str()) else {
+            // a tracker we can't read can't be allowed either
+            return !self.allow.is_empty();
+        };
+        let listed = |hosts: &[String]| {
+            hosts
+                .iter()
+                .any(|domain| crate::text::is_subdomain(host, domain))
+        };
+        match self.allow.is_empty() {
+            false => !listed(&self.allow),
+            true => listed(&self.deny),
+        }
+    }
+}
+
+/// Keys of URLs without host that are never removed, as they carry the link itself.
+const PRESERVED_KEYS: &[(&str, &str)] = &[
+    ("scheme:mailto", "^(?:to|cc|bcc|subject|body)$"),
+    ("scheme:magnet", "^(?:xt|dn)$"),
+];
+
+/// Represent rule for a single domain.
+#[derive(Clone, Debug, Default)]
 pub struct Rule {
     pub redirect: bool,
-    pub rules: Vec<regex::Regex>,
-    pub post_hooks: Vec<String>,
-}
-
-/// Rules is a KV map with K as full-formed URL, V as clean rules.
-pub type Rules = HashMap<String, Arc<Rule>>;
-
-pub fn parse_from_file<P: AsRef<Path> + Debug>(path: P) -> Rules {
-    let content = std::fs::read_to_string(path.as_ref())
-        .unwrap_or_else(|error| panic!("fail to read from {path:?}: {error}"));
-    parse(&content)
-}
-
-/// Parse rules configuration file from given `location`.
-///
-/// # Error
-///
-/// Panic if
-///   * fail to read the file content
-///   * fail to parse content into expected struct
-///   * regexp is invalid
-pub fn parse(content: &str) -> Rules {
-    let config: HashMap<String, ConfigData> = toml::from_str(&content)
-        .unwrap_or_else(|error| panic!("fail to parse data into rules: {error}"));
-
-    let mut rules = HashMap::new();
-    config.into_iter().for_each(|(base, data)| {
-        let rule = Arc::new(Rule {
-            redirect: data.redirect,
-            rules: data
-                .ban
-                .into_iter()
-                .map(|re| {
-                    // Use `unwrap_or_else()` instead of `expect` to avoid overhead
-                    regex::Regex::new(&re).unwrap_or_else(|error| {
-                        panic!("Invalid regexp: '{re}' for URL: {base}\n\nError: {error}")
-                    })
+    /// Only redirect the URLs whose path match this pattern
+    pub redirect_path: Option<Arc<regex::Regex>>,
+    pub redirect_mode: RedirectMode,
+    /// Ban patterns, identical patterns across rules share one compiled regex
+    pub rules: Vec<Arc<regex::Regex>>,
+    /// How many keys each pattern in `rules` removed, by index
+    #[cfg(feature = "usage_stats")]
+    pub hits: Hits,
+    /// Whether the ban patterns and the triggers of conditional groups ignore case
+    pub case_insensitive: bool,
+    pub conditional: Vec<ConditionalBan>,
+    /// Ban patterns of the `;key=value` matrix parameters in path segments
+    pub matrix: Vec<Arc<regex::Regex>>,
+    /// Filter of the trackers of magnet links
+    pub trackers: Option<TrackerFilter>,
+    pub post_hooks: Vec<HookCall>,
+    /// Remove the whole query regardless of keys
+    pub drop_all_query: bool,
+    /// Remove the fragment
+    pub drop_fragment: bool,
+    /// Filter the parameters of the fragment, like `#/route?utm_source=x` of single-page apps
+    pub clean_fragment: bool,
+    /// What to do when only the origin is left, the one of the cleaner when `None`
+    pub if_reduced_to_origin: Option<OriginPolicy>,
+    /// Keys matching these patterns are never removed, even if banned by the default rule
+    pub keep: Vec<Arc<regex::Regex>>,
+    /// Apply the bans of the default rule in addition to this rule
+    pub include_default: bool,
+    /// The table name this rule is defined with
+    pub base: String,
+    /// Subdomains this rule is expanded to
+    pub sub: Vec<String>,
+    /// Where the table of this rule is defined
+    pub location: Location,
+}
+
+impl Rule {
+    /// Whether the given URL should be redirected before cleaning.
+    pub fn needs_redirect(&self, url: &url::Url) -> bool {
+        self.redirect
+            && self
+                .redirect_path
+                .as_ref()
+                .is_none_or(|re| re.is_match(url.path()))
+    }
+}
+
+/// Location of a value in the ruleset source.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    /// The file the ruleset is read from, `None` if it is parsed from a string
+    pub file: Option<PathBuf>,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column number, counted in characters
+    pub column: usize,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Rules added at runtime have no source
+        if self.file.is_none() && self.line == 0 {
+            return f.write_str("<runtime>");
+        }
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Error when reading or parsing the ruleset.
+#[derive(Debug, thiserror::Error)]
+pub enum RuleParseError {
+    #[error("fail to read rules from {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{}fail to parse data into rules: {message}", location.as_ref().map(|l| format!("{l}: ")).unwrap_or_default())]
+    Syntax {
+        location: Option<Location>,
+        message: String,
+    },
+    #[error("{location}: invalid regexp '{pattern}' for URL {domain}: {source}")]
+    InvalidRegex {
+        location: Location,
+        domain: String,
+        pattern: String,
+        source: regex::Error,
+    },
+    #[error("{location}: invalid domain '{domain}': {message}")]
+    InvalidDomain {
+        location: Location,
+        domain: String,
+        message: String,
+    },
+    #[error("{location}: unknown hook '{hook}' for URL {domain}{}", suggestion.as_ref().map(|s| format!(", did you mean '{s}'?")).unwrap_or_default())]
+    UnknownHook {
+        location: Location,
+        domain: String,
+        hook: String,
+        suggestion: Option<String>,
+    },
+    #[error("{location}: invalid hook '{hook}' for URL {domain}: {message}")]
+    InvalidHookArgs {
+        location: Location,
+        domain: String,
+        hook: String,
+        message: String,
+    },
+    #[error("ruleset has warnings in strict mode: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Strict(Vec<RuleWarning>),
+    #[cfg(feature = "remote_rules")]
+    #[error("fail to fetch rules from {url}: {message}")]
+    Fetch { url: String, message: String },
+    #[cfg(feature = "remote_rules")]
+    #[error("rules from {url} don't match the pinned sha256 {expected}, got {actual}")]
+    Sha256Mismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// The ruleset text and where it comes from, to locate byte offsets.
+struct Source<'a> {
+    file: Option<&'a Path>,
+    content: &'a str,
+}
+
+impl Source<'_> {
+    fn locate(&self, offset: usize) -> Location {
+        let before = &self.content[..offset.min(self.content.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Location {
+            file: self.file.map(Path::to_path_buf),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+/// Top level tables in document order, with the span of their names.
+struct Entries {
+    settings: Option<Settings>,
+    rules: Vec<(Spanned<String>, ConfigData)>,
+    /// Tables under `[pattern]`, keyed by a full-URL regex
+    patterns: Vec<(Spanned<String>, ConfigData)>,
+}
+
+/// Tables of rules in document order, with the span of their names.
+struct Tables(Vec<(Spanned<String>, ConfigData)>);
+
+impl<'de> Deserialize<'de> for Tables {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Tables;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("tables of URL pattern rules")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut tables = Vec::new();
+                while let Some(key) = map.next_key::<Spanned<String>>()? {
+                    tables.push((key, map.next_value()?));
+                }
+                Ok(Tables(tables))
+            }
+        }
+
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Entries {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Entries;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("tables of domain rules")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut settings = None;
+                let mut rules = Vec::new();
+                let mut patterns = Vec::new();
+                while let Some(key) = map.next_key::<Spanned<String>>()? {
+                    match key.get_ref().as_str() {
+                        "settings" => settings = Some(map.next_value()?),
+                        "pattern" => patterns.extend(map.next_value::<Tables>()?.0),
+                        _ => rules.push((key, map.next_value()?)),
+                    }
+                }
+                Ok(Entries {
+                    settings,
+                    rules,
+                    patterns,
                 })
-                .collect(),
-            post_hooks: data.post_hooks.unwrap_or_default(),
-        });
-        if let Some(sub) = data.sub {
-            sub.into_iter().for_each(|sub_domain| {
-                rules.insert(format!("{sub_domain}.{base}"), Arc::clone(&rule));
+            }
+        }
+
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+/// Read-only view of the rule registered for a domain.
+/// It keeps the rule alive, so it stays valid even if the ruleset changes later.
+#[derive(Clone, Debug)]
+pub struct RuleView {
+    domain: String,
+    rule: Arc<Rule>,
+}
+
+impl RuleView {
+    pub(crate) fn new(domain: &str, rule: &Arc<Rule>) -> Self {
+        Self {
+            domain: domain.to_string(),
+            rule: Arc::clone(rule),
+        }
+    }
+
+    /// The domain this rule is registered for.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Whether URLs of this domain are redirected before cleaning.
+    pub fn redirect(&self) -> bool {
+        self.rule.redirect
+    }
+
+    /// How the redirection is resolved.
+    pub fn redirect_mode(&self) -> RedirectMode {
+        self.rule.redirect_mode
+    }
+
+    /// The pattern limiting redirection to matching paths, if any.
+    pub fn redirect_path(&self) -> Option<&str> {
+        self.rule.redirect_path.as_ref().map(|re| re.as_str())
+    }
+
+    /// The ban patterns as written in the ruleset.
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        self.rule.rules.iter().map(|re| re.as_str())
+    }
+
+    /// Whether the whole query is removed.
+    pub fn drop_all_query(&self) -> bool {
+        self.rule.drop_all_query
+    }
+
+    /// Whether the fragment is removed.
+    pub fn drop_fragment(&self) -> bool {
+        self.rule.drop_fragment
+    }
+
+    /// Whether the parameters of the fragment are filtered.
+    pub fn clean_fragment(&self) -> bool {
+        self.rule.clean_fragment
+    }
+
+    /// What to do when only the origin is left, when set by the rule.
+    pub fn if_reduced_to_origin(&self) -> Option<OriginPolicy> {
+        self.rule.if_reduced_to_origin
+    }
+
+    /// Patterns of the keys that are never removed.
+    pub fn keep(&self) -> impl Iterator<Item = &str> {
+        self.rule.keep.iter().map(|re| re.as_str())
+    }
+
+    /// The ban patterns of path matrix parameters as written in the ruleset.
+    pub fn matrix_patterns(&self) -> impl Iterator<Item = &str> {
+        self.rule.matrix.iter().map(|re| re.as_str())
+    }
+
+    /// Whether the ban patterns ignore case.
+    pub fn case_insensitive(&self) -> bool {
+        self.rule.case_insensitive
+    }
+
+    /// Whether the bans of the default rule also apply.
+    pub fn include_default(&self) -> bool {
+        self.rule.include_default
+    }
+
+    /// Names of the post hooks, in execution order.
+    pub fn post_hooks(&self) -> Vec<&str> {
+        self.rule
+            .post_hooks
+            .iter()
+            .map(|hook| hook.name.as_str())
+            .collect()
+    }
+
+    /// Arguments given to the named post hook, `None` if the rule doesn't use the hook.
+    pub fn hook_args(&self, name: &str) -> Option<&HashMap<String, String>> {
+        self.rule
+            .post_hooks
+            .iter()
+            .find(|hook| hook.name == name)
+            .map(|hook| &hook.args)
+    }
+
+    /// The table name in the ruleset this rule is defined with.
+    pub fn defined_as(&self) -> &str {
+        &self.rule.base
+    }
+
+    /// The subdomains listed in `sub` of the rule definition.
+    pub fn subdomains(&self) -> &[String] {
+        &self.rule.sub
+    }
+
+    /// Where the rule is defined in the ruleset source.
+    pub fn defined_at(&self) -> &Location {
+        &self.rule.location
+    }
+
+    /// Whether this domain is registered by expanding the `sub` list.
+    pub fn is_subdomain_expansion(&self) -> bool {
+        idna::domain_to_ascii(&self.rule.base).map_or(true, |base| base != self.domain)
+    }
+}
+
+/// Rules is the whole compiled ruleset.
+#[derive(Clone, Debug, Default)]
+pub struct Rules {
+    /// KV map with K as full-formed domain, V as clean rules.
+    pub hosts: HashMap<String, Arc<Rule>>,
+    /// Rules matched by a regex over the whole URL, in document order. They are consulted
+    /// when no host rule matches, before falling back to the default rule.
+    pub patterns: Vec<(Arc<regex::Regex>, Arc<Rule>)>,
+    /// All of `patterns` compiled together, so one pass over the URL finds the first match
+    /// instead of trying each regex in turn. Built on the first lookup.
+    pattern_set: std::sync::OnceLock<Option<regex::RegexSet>>,
+}
+
+impl Rules {
+    /// Every rule by the key it is registered with, the domain or the pattern.
+    fn entries(&self) -> HashMap<&str, &Rule> {
+        let hosts = self
+            .hosts
+            .iter()
+            .map(|(domain, rule)| (domain.as_str(), &**rule));
+        let patterns = (self.patterns.iter()).map(|(pattern, rule)| (pattern.as_str(), &**rule));
+        hosts.chain(patterns).collect()
+    }
+
+    /// Find the rule of the URL, by host and then by pattern. The key is the domain or the
+    /// pattern the rule is registered with.
+    pub fn find<'a>(&'a self, url: &url::Url) -> Option<(&'a str, &'a Arc<Rule>)> {
+        if url.cannot_be_a_base() {
+            return self
+                .hosts
+                .get_key_value(&format!("scheme:{}", url.scheme()))
+                .map(|(key, rule)| (key.as_str(), rule));
+        }
+        let domain = crate::text::lookup_domain(url);
+        if let Some((domain, rule)) = domain.and_then(|d| self.hosts.get_key_value(d)) {
+            return Some((domain, rule));
+        }
+        let (pattern, rule) = match self.pattern_set() {
+            Some(set) => self
+                .patterns
+                .get(set.matches(url.as_str()).iter().next()?)?,
+            None => (self.patterns.iter()).find(|(pattern, _)| pattern.is_match(url.as_str()))?,
+        };
+        Some((pattern.as_str(), rule))
+    }
+
+    /// The set of the pattern rules, `None` when it can't be compiled within the size limit
+    /// of the regex crate or is out of date, in which case each pattern is tried in turn.
+    fn pattern_set(&self) -> Option<&regex::RegexSet> {
+        self.pattern_set
+            .get_or_init(|| {
+                regex::RegexSet::new(self.patterns.iter().map(|(pattern, _)| pattern.as_str())).ok()
             })
-        } else {
-            rules.insert(base, rule);
+            .as_ref()
+            .filter(|set| set.len() == self.patterns.len())
+    }
+}
+
+/// Rules consulted before the ones of the cleaner for a single call of
+/// [`UrlCleaner::clear_scoped`](crate::UrlCleaner::clear_scoped), like the policy of a
+/// tenant. Cloning is cheap, the rules are shared behind an [`Arc`].
+#[derive(Clone, Debug, Default)]
+pub struct RuleOverlay(pub(crate) Arc<Rules>);
+
+impl RuleOverlay {
+    /// Parse the overlay from a toml document, in the same format as the ruleset.
+    pub fn from_toml(data: &str) -> Result<Self, RuleParseError> {
+        let (rules, _) = parse_with(data, None, &ParseOptions::default())?;
+        Ok(Self(Arc::new(rules)))
+    }
+
+    /// Read the overlay from the given toml file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, RuleParseError> {
+        let path = path.as_ref();
+        let (rules, _) = parse_with(&read_file(path)?, Some(path), &ParseOptions::default())?;
+        Ok(Self(Arc::new(rules)))
+    }
+}
+
+/// Options controlling how the ruleset is parsed.
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    /// Register the base domain of an entry together with its `sub` list. When disabled,
+    /// only the listed subdomains are registered, as in older versions.
+    pub register_base_with_sub: bool,
+    /// Skip invalid entries and ban patterns with a warning instead of rejecting the whole
+    /// ruleset. TOML syntax errors still reject it.
+    pub lenient: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            register_base_with_sub: true,
+            lenient: false,
+        }
+    }
+}
+
+/// Non fatal issues found while parsing the ruleset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleWarning {
+    /// `domain` is registered by entry `previous` and then overwritten by a later entry `by`
+    /// defined at `location`.
+    Overwritten {
+        domain: String,
+        previous: String,
+        by: String,
+        location: Location,
+    },
+    /// The ban `pattern` of `domain` at `location` is invalid, it is skipped in lenient mode
+    /// and the rest of the entry is kept.
+    SkippedPattern {
+        domain: String,
+        pattern: String,
+        location: Location,
+        message: String,
+    },
+    /// The entry for `domain` at `location` is invalid, it is skipped in lenient mode.
+    SkippedEntry {
+        domain: String,
+        location: Location,
+        message: String,
+    },
+    /// The post hook `hook` of `domain` at `location` is listed again with the same
+    /// arguments, the repeat is dropped.
+    DuplicateHook {
+        domain: String,
+        hook: String,
+        location: Location,
+    },
+    /// The hook `hook` of `domain` at `location` is not compiled in this build, it is kept
+    /// in the rule but does nothing until `feature` is enabled.
+    HookNotCompiled {
+        domain: String,
+        hook: String,
+        feature: String,
+        location: Location,
+    },
+}
+
+impl RuleWarning {
+    fn skipped_pattern(error: RuleParseError) -> Option<Self> {
+        match error {
+            RuleParseError::InvalidRegex {
+                location,
+                domain,
+                pattern,
+                source,
+            } => Some(Self::SkippedPattern {
+                domain,
+                pattern,
+                location,
+                message: source.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RuleWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Overwritten {
+                domain,
+                previous,
+                by,
+                location,
+            } => write!(
+                f,
+                "{location}: rule for {domain} defined by [\"{previous}\"] is overwritten by [\"{by}\"]"
+            ),
+            Self::SkippedPattern {
+                domain,
+                pattern,
+                location,
+                message,
+            } => write!(
+                f,
+                "{location}: skipped invalid regexp '{pattern}' for URL {domain}: {message}"
+            ),
+            Self::SkippedEntry {
+                domain,
+                location,
+                message,
+            } => write!(f, "{location}: skipped the rule for {domain}: {message}"),
+            Self::DuplicateHook {
+                domain,
+                hook,
+                location,
+            } => write!(
+                f,
+                "{location}: hook '{hook}' is listed twice for URL {domain}, the repeat is ignored"
+            ),
+            Self::HookNotCompiled {
+                domain,
+                hook,
+                feature,
+                location,
+            } => write!(
+                f,
+                "{location}: hook '{hook}' for URL {domain} is not compiled, it requires the '{feature}' feature"
+            ),
+        }
+    }
+}
+
+pub fn read_file(path: &Path) -> Result<String, RuleParseError> {
+    std::fs::read_to_string(path).map_err(|source| RuleParseError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Parse rules configuration. `file` is only used for error locations. Entries are
+/// registered in document order, so when two entries resolve to the same domain the later
+/// one wins and a warning is reported.
+pub fn parse_with(
+    content: &str,
+    file: Option<&Path>,
+    options: &ParseOptions,
+) -> Result<(Rules, Vec<RuleWarning>), RuleParseError> {
+    parse_documents(&[(content, file)], options)
+}
+
+/// Parse several documents into one ruleset, as if they were concatenated: a later entry
+/// overwrites the domains of earlier ones with a warning, and the `[settings]` of a document
+/// also apply to the following documents that don't have their own.
+pub fn parse_documents(
+    documents: &[(&str, Option<&Path>)],
+    options: &ParseOptions,
+) -> Result<(Rules, Vec<RuleWarning>), RuleParseError> {
+    let mut merged = Merged::default();
+    for &(content, file) in documents {
+        parse_document(content, file, options, &mut merged)?;
+    }
+    Ok((merged.rules, merged.warnings))
+}
+
+/// The state carried from one document to the next by [`parse_documents`].
+#[derive(Default)]
+struct Merged {
+    rules: Rules,
+    warnings: Vec<RuleWarning>,
+    settings: Settings,
+    /// rulesets repeat the same patterns across many domains, compile each only once
+    compiled: HashMap<(String, bool), Arc<regex::Regex>>,
+}
+
+fn parse_document(
+    content: &str,
+    file: Option<&Path>,
+    options: &ParseOptions,
+    merged: &mut Merged,
+) -> Result<(), RuleParseError> {
+    let source = Source { file, content };
+    let Entries {
+        settings,
+        rules: config,
+        patterns: pattern_config,
+    } = toml::from_str(content).map_err(|error| RuleParseError::Syntax {
+        location: error.span().map(|span| source.locate(span.start)),
+        message: error.message().to_string(),
+    })?;
+
+    if let Some(settings) = settings {
+        merged.settings = settings;
+    }
+    // domain rules first, pattern rules keep their document order among themselves
+    let entries = (config.into_iter().map(|(key, data)| (false, key, data))).chain(
+        pattern_config
+            .into_iter()
+            .map(|(key, data)| (true, key, data)),
+    );
+    for (is_pattern, base, data) in entries {
+        let location = source.locate(base.span().start);
+        let domain = base.get_ref().clone();
+        match parse_entry(&source, options, merged, is_pattern, base, data) {
+            Ok(()) => {}
+            // in lenient mode, a bad entry is skipped and reported
+            Err(error) if options.lenient => merged.warnings.push(RuleWarning::SkippedEntry {
+                domain,
+                location,
+                message: error.to_string(),
+            }),
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse one table of the ruleset into `merged`.
+fn parse_entry(
+    source: &Source,
+    options: &ParseOptions,
+    merged: &mut Merged,
+    is_pattern: bool,
+    base: Spanned<String>,
+    data: ConfigData,
+) -> Result<(), RuleParseError> {
+    let Merged {
+        rules,
+        warnings,
+        settings,
+        compiled,
+    } = merged;
+    let location = source.locate(base.span().start);
+    let key_span = base.span();
+    let base = base.into_inner();
+
+    let mut compile = |re: &str, span: std::ops::Range<usize>, case_insensitive: bool| {
+        let key = (re.to_string(), case_insensitive);
+        if let Some(regex) = compiled.get(&key) {
+            return Ok(Arc::clone(regex));
+        }
+        let regex =
+            compile_regex(re, case_insensitive).map_err(|error| RuleParseError::InvalidRegex {
+                location: source.locate(span.start),
+                domain: base.clone(),
+                pattern: re.to_string(),
+                source: error,
+            })?;
+        compiled.insert(key, Arc::clone(&regex));
+        Ok(regex)
+    };
+    let case_insensitive = data.case_insensitive;
+    let mut patterns = Vec::new();
+    let mut conditional = Vec::new();
+    // in lenient mode, a bad ban pattern is skipped and the rest of the entry kept
+    let mut skipped = Vec::new();
+    let mut compile_ban =
+        |re: &str, span: std::ops::Range<usize>| match compile(re, span, case_insensitive) {
+            Ok(regex) => Ok(Some(regex)),
+            Err(error) if options.lenient => {
+                skipped.push(error);
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        };
+    for entry in data.ban {
+        let span = entry.span();
+        match entry.into_inner() {
+            BanEntry::Pattern(re) => patterns.extend(compile_ban(&re, span)?),
+            BanEntry::Conditional { keys, when_present } => {
+                let mut compiled_keys = Vec::new();
+                for re in &keys {
+                    compiled_keys.extend(compile_ban(re, span.clone())?);
+                }
+                conditional.push(ConditionalBan {
+                    keys: compiled_keys,
+                    when_present,
+                });
+            }
         }
+    }
+
+    let mut post_hooks = Vec::new();
+    let mut hook_warnings = Vec::new();
+    for entry in data.post_hooks.unwrap_or_default() {
+        let span = entry.span();
+        let hook = HookCall::from(entry.into_inner());
+        #[cfg(feature = "hooks-core")]
+        match crate::hooks::check(&hook.name, &hook.args) {
+            Ok(()) => {}
+            Err(crate::hooks::HookProblem::Unknown { suggestion }) => {
+                return Err(RuleParseError::UnknownHook {
+                    location: source.locate(span.start),
+                    domain: base.clone(),
+                    hook: hook.name,
+                    suggestion: suggestion.map(str::to_string),
+                })
+            }
+            Err(crate::hooks::HookProblem::InvalidArgs(message)) => {
+                return Err(RuleParseError::InvalidHookArgs {
+                    location: source.locate(span.start),
+                    domain: base.clone(),
+                    hook: hook.name,
+                    message,
+                })
+            }
+            Err(crate::hooks::HookProblem::NotCompiled { feature }) => {
+                hook_warnings.push(RuleWarning::HookNotCompiled {
+                    domain: base.clone(),
+                    hook: hook.name.clone(),
+                    feature: feature.to_string(),
+                    location: source.locate(span.start),
+                })
+            }
+        }
+        // hooks run in list order, a repeated call would only run the same rewrite again
+        if post_hooks.contains(&hook) {
+            hook_warnings.push(RuleWarning::DuplicateHook {
+                domain: base.clone(),
+                hook: hook.name,
+                location: source.locate(span.start),
+            });
+            continue;
+        }
+        post_hooks.push(hook);
+    }
+
+    let redirect_path = data
+        .redirect_path
+        .map(|re| compile(re.get_ref(), re.span(), false))
+        .transpose()?;
+
+    let mut keep = data
+        .keep
+        .iter()
+        .map(|re| compile(re.get_ref(), re.span(), false))
+        .collect::<Result<Vec<_>, _>>()?;
+    let scheme_key = base
+        .starts_with("scheme:")
+        .then(|| base.to_ascii_lowercase());
+    let preserved = PRESERVED_KEYS
+        .iter()
+        .find(|(key, _)| scheme_key.as_deref() == Some(key));
+    if let Some((_, preserved)) = preserved {
+        keep.push(compile(preserved, key_span.clone(), false)?);
+    }
+
+    let trackers = (data.tracker_allow.is_some() || data.tracker_deny.is_some()).then(|| {
+        let hosts = |hosts: Option<Vec<String>>| {
+            hosts
+                .into_iter()
+                .flatten()
+                .map(|host| host.trim_start_matches('.').to_ascii_lowercase())
+                .collect()
+        };
+        TrackerFilter {
+            allow: hosts(data.tracker_allow),
+            deny: hosts(data.tracker_deny),
+        }
+    });
+    if trackers.is_some() && scheme_key.as_deref() != Some("scheme:magnet") {
+        return Err(RuleParseError::InvalidDomain {
+            location,
+            domain: base,
+            message: "`tracker_allow` and `tracker_deny` only apply to `scheme:magnet`".to_string(),
+        });
+    }
+
+    let rule = Arc::new(Rule {
+        redirect: data.redirect || redirect_path.is_some() || data.redirect_mode.is_some(),
+        redirect_path,
+        redirect_mode: data.redirect_mode.unwrap_or_default(),
+        #[cfg(feature = "usage_stats")]
+        hits: Hits::new(patterns.len()),
+        rules: patterns,
+        case_insensitive,
+        conditional,
+        matrix: data
+            .ban_matrix
+            .iter()
+            .map(|re| compile(re.get_ref(), re.span(), case_insensitive))
+            .collect::<Result<_, _>>()?,
+        trackers,
+        post_hooks,
+        drop_all_query: data.drop_all_query,
+        drop_fragment: data.drop_fragment,
+        clean_fragment: data.clean_fragment,
+        if_reduced_to_origin: data.if_reduced_to_origin,
+        keep,
+        include_default: base != "default"
+            && data.include_default.unwrap_or(settings.default_is_global),
+        base: base.clone(),
+        sub: data
+            .sub
+            .iter()
+            .flatten()
+            .map(|sub| sub.get_ref().clone())
+            .collect(),
+        location: location.clone(),
     });
 
-    rules
+    if is_pattern {
+        if data.sub.is_some() || data.include_bare.is_some() {
+            return Err(RuleParseError::InvalidDomain {
+                location,
+                domain: base,
+                message: "`sub` and `include_bare` don't apply to URL pattern rules".to_string(),
+            });
+        }
+        rules
+            .patterns
+            .push((compile(&base, key_span, false)?, rule));
+        warnings.extend(skipped.into_iter().filter_map(RuleWarning::skipped_pattern));
+        warnings.extend(hook_warnings);
+        return Ok(());
+    }
+
+    // URLs without host, like `mailto:`, are matched by their scheme
+    let is_scheme = scheme_key.is_some();
+    if is_scheme && (data.sub.is_some() || data.include_bare.is_some()) {
+        return Err(RuleParseError::InvalidDomain {
+            location,
+            domain: base,
+            message: "`sub` and `include_bare` don't apply to scheme rules".to_string(),
+        });
+    }
+
+    let mut domains = Vec::new();
+    if let Some(sub) = data.sub {
+        for sub_domain in sub {
+            let domain = format!("{}.{base}", sub_domain.get_ref());
+            check_sub(sub_domain.get_ref()).map_err(|message| RuleParseError::InvalidDomain {
+                location: source.locate(sub_domain.span().start),
+                domain: domain.clone(),
+                message: message.to_string(),
+            })?;
+            domains.push(domain);
+        }
+        if data.include_bare.unwrap_or(options.register_base_with_sub) {
+            domains.push(base);
+        }
+    } else {
+        domains.push(base);
+    }
+
+    // converted first, so a bad domain leaves nothing registered
+    let domains = domains
+        .into_iter()
+        .map(|domain| match is_scheme {
+            true => Ok((domain.to_ascii_lowercase(), domain)),
+            false => match to_ascii(&domain) {
+                Ok(ascii) => Ok((ascii, domain)),
+                Err(message) => Err(RuleParseError::InvalidDomain {
+                    location: location.clone(),
+                    domain,
+                    message,
+                }),
+            },
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    warnings.extend(skipped.into_iter().filter_map(RuleWarning::skipped_pattern));
+    warnings.extend(hook_warnings);
+    for (ascii, domain) in domains {
+        if let Some(previous) = rules.hosts.insert(ascii, Arc::clone(&rule)) {
+            warnings.push(RuleWarning::Overwritten {
+                domain,
+                previous: previous.base.clone(),
+                by: rule.base.clone(),
+                location: location.clone(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Compile the pattern, keeping it as written for [`regex::Regex::as_str`].
+pub fn compile_regex(
+    pattern: &str,
+    case_insensitive: bool,
+) -> Result<Arc<regex::Regex>, regex::Error> {
+    regex::RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map(Arc::new)
+}
+
+/// Reject `sub` entries that can't form a domain with the base.
+fn check_sub(sub: &str) -> Result<(), &'static str> {
+    if sub.is_empty() {
+        return Err("empty entry in `sub`, remove it or use `include_bare` for the base domain");
+    }
+    if sub.chars().any(char::is_whitespace) {
+        return Err("entry in `sub` contains whitespace");
+    }
+    if sub.starts_with('.') || sub.ends_with('.') || sub.contains("..") {
+        return Err("entry in `sub` has an empty label");
+    }
+    Ok(())
+}
+
+/// Convert the domain into its ASCII (punycode) form, the same form `Url::domain()` returns,
+/// so rules written in Unicode can match.
+fn to_ascii(domain: &str) -> Result<String, String> {
+    idna::domain_to_ascii(domain).map_err(|error| error.to_string())
+}
+
+#[test]
+fn test_idn_domain() {
+    let (rules, _) = parse_with(
+        r#"
+["пример.рф"]
+ban = ["utm_source"]
+
+["xn--wgv71a119e.jp"]
+ban = ["utm_source"]
+
+["Example.COM"]
+sub = ["www"]
+ban = ["utm_source"]
+"#,
+        None,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    assert!(rules.hosts.contains_key("xn--e1afmkfd.xn--p1ai"));
+    assert!(rules.hosts.contains_key("xn--wgv71a119e.jp"));
+    assert!(rules.hosts.contains_key("www.example.com"));
+}
+
+#[test]
+fn test_sub_registers_base() {
+    let content = r#"
+["smzdm.com"]
+sub = ["post.m"]
+ban = ["zdm_ss"]
+"#;
+    let (rules, warnings) = parse_with(content, None, &ParseOptions::default()).unwrap();
+    assert!(warnings.is_empty());
+    assert!(rules.hosts.contains_key("post.m.smzdm.com"));
+    assert!(rules.hosts.contains_key("smzdm.com"));
+
+    let legacy = ParseOptions {
+        register_base_with_sub: false,
+        ..Default::default()
+    };
+    let (rules, _) = parse_with(content, None, &legacy).unwrap();
+    assert!(rules.hosts.contains_key("post.m.smzdm.com"));
+    assert!(!rules.hosts.contains_key("smzdm.com"));
+}
+
+#[test]
+fn test_overwrite_warning() {
+    let (rules, warnings) = parse_with(
+        r#"
+["bilibili.com"]
+sub = ["www", "m"]
+ban = ["vd_source"]
+
+["www.bilibili.com"]
+ban = ["spm_id_from"]
+"#,
+        None,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        warnings,
+        [RuleWarning::Overwritten {
+            domain: "www.bilibili.com".to_string(),
+            previous: "bilibili.com".to_string(),
+            by: "www.bilibili.com".to_string(),
+            location: Location {
+                file: None,
+                line: 6,
+                column: 2,
+            },
+        }]
+    );
+    // later entry wins
+    assert_eq!(rules.hosts["www.bilibili.com"].base, "www.bilibili.com");
+    assert_eq!(rules.hosts["m.bilibili.com"].base, "bilibili.com");
+
+    let (_, warnings) = parse_with(
+        &std::fs::read_to_string("./rules.toml").unwrap(),
+        None,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    // the bundled ruleset references every built-in hook, including the opt-in ones
+    assert!(
+        (warnings.iter()).all(|warning| matches!(warning, RuleWarning::HookNotCompiled { .. })),
+        "{warnings:?}"
+    );
+}
+
+#[test]
+fn test_error_location() {
+    let content = r#"
+["example.com"]
+ban = ["utm_source"]
+
+["bilibili.com"]
+ban = [
+  "vd_source",
+  "share_(",
+]
+"#;
+    let error = parse_with(
+        content,
+        Some(Path::new("rules.toml")),
+        &ParseOptions::default(),
+    )
+    .unwrap_err();
+    match &error {
+        RuleParseError::InvalidRegex {
+            location,
+            domain,
+            pattern,
+            ..
+        } => {
+            assert_eq!(
+                *location,
+                Location {
+                    file: Some(PathBuf::from("rules.toml")),
+                    line: 8,
+                    column: 3,
+                }
+            );
+            assert_eq!(domain, "bilibili.com");
+            assert_eq!(pattern, "share_(");
+        }
+        otherwise => panic!("unexpected error {otherwise:?}"),
+    }
+    assert!(error
+        .to_string()
+        .starts_with("rules.toml:8:3: invalid regexp"));
+
+    let (rules, _) = parse_with(
+        content.replace("share_(", "share_").as_str(),
+        None,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(rules.hosts["bilibili.com"].location.line, 5);
+    assert_eq!(rules.hosts["bilibili.com"].location.column, 2);
+
+    let error = parse_with("[a\nban = 1", None, &ParseOptions::default()).unwrap_err();
+    assert!(matches!(
+        error,
+        RuleParseError::Syntax {
+            location: Some(Location { line: 1, .. }),
+            ..
+        }
+    ));
+}
+
+#[cfg(feature = "hooks-core")]
+#[test]
+fn test_hook_validation() {
+    let parse = |content: &str| parse_with(content, None, &ParseOptions::default()).map(|_| ());
+
+    let err = parse(
+        r#"
+["twitter.com"]
+post_hooks = ["fixup_twiter"]
+"#,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        &err,
+        RuleParseError::UnknownHook { domain, hook, suggestion, .. }
+            if domain == "twitter.com" && hook == "fixup_twiter" && suggestion.as_deref() == Some("fixup_twitter")
+    ));
+    assert_eq!(
+        err.to_string(),
+        "3:15: unknown hook 'fixup_twiter' for URL twitter.com, did you mean 'fixup_twitter'?"
+    );
+
+    let err = parse(
+        r#"
+["example.com"]
+post_hooks = ["completely_unrelated_name"]
+"#,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        RuleParseError::UnknownHook {
+            suggestion: None,
+            ..
+        }
+    ));
+
+    let err = parse(
+        r#"
+["example.com"]
+post_hooks = ["test_set_path"]
+"#,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "3:15: invalid hook 'test_set_path' for URL example.com: missing required argument 'path'"
+    );
+
+    let err = parse(
+        r#"
+["tiktok.com"]
+post_hooks = [{ name = "tiktok_canonical", args = { hots = "a.example" } }]
+"#,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        RuleParseError::InvalidHookArgs { message, .. } if message == "unknown argument 'hots'"
+    ));
+
+    parse(
+        r#"
+["example.com"]
+post_hooks = [{ name = "test_set_path", args = { path = "/a" } }, "bv_to_av"]
+"#,
+    )
+    .unwrap();
+}
+
+#[cfg(feature = "hook-twitter")]
+#[test]
+fn test_duplicate_hooks() {
+    let (rules, warnings) = parse_with(
+        r#"
+["example.com"]
+post_hooks = [
+    "fixup_twitter",
+    { name = "test_set_path", args = { path = "/a" } },
+    "fixup_twitter",
+    { name = "test_set_path", args = { path = "/b" } },
+]
+"#,
+        None,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    let names: Vec<_> = (rules.hosts["example.com"].post_hooks.iter())
+        .map(|hook| hook.name.as_str())
+        .collect();
+    assert_eq!(names, ["fixup_twitter", "test_set_path", "test_set_path"]);
+    assert_eq!(warnings.len(), 1);
+    assert!(matches!(
+        &warnings[0],
+        RuleWarning::DuplicateHook { domain, hook, location }
+            if domain == "example.com" && hook == "fixup_twitter" && location.line == 6
+    ));
+}
+
+#[cfg(all(feature = "hooks-core", not(feature = "hook-bilibili")))]
+#[test]
+fn test_hook_not_compiled() {
+    let content = "[\"bilibili.com\"]\npost_hooks = [\"bv_to_av\"]\n";
+    let (rules, warnings) = parse_with(content, None, &ParseOptions::default()).unwrap();
+    assert_eq!(rules.hosts["bilibili.com"].post_hooks.len(), 1);
+    assert_eq!(
+        warnings.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        ["2:15: hook 'bv_to_av' for URL bilibili.com is not compiled, it requires the 'hook-bilibili' feature"]
+    );
+
+    let strict = crate::UrlCleaner::builder()
+        .rules_from_toml(content)
+        .strict(true)
+        .build();
+    let Err(err @ RuleParseError::Strict(_)) = strict else {
+        panic!("missing hooks are fatal in strict mode");
+    };
+    assert!(err.to_string().contains("'hook-bilibili'"), "{err}");
+}
+
+#[test]
+fn test_include_bare() {
+    let (rules, _) = parse_with(
+        r#"
+["bilibili.com"]
+sub = ["www", "m"]
+
+["jd.com"]
+sub = ["item.m"]
+include_bare = false
+"#,
+        None,
+        &ParseOptions {
+            register_base_with_sub: false,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(!rules.hosts.contains_key("bilibili.com"));
+    assert!(rules.hosts.contains_key("m.bilibili.com"));
+    assert!(!rules.hosts.contains_key("jd.com"));
+
+    let (rules, _) = parse_with(
+        r#"
+["bilibili.com"]
+sub = ["www"]
+include_bare = true
+
+["jd.com"]
+sub = ["item.m"]
+include_bare = false
+"#,
+        None,
+        &ParseOptions {
+            register_base_with_sub: false,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(rules.hosts.contains_key("bilibili.com"));
+    assert!(rules.hosts.contains_key("item.m.jd.com"));
+    assert!(!rules.hosts.contains_key("jd.com"));
+}
+
+#[test]
+fn test_malformed_sub() {
+    for (sub, message) in [
+        ("\"\"", "empty entry"),
+        ("\"m .\"", "whitespace"),
+        ("\"m..a\"", "empty label"),
+        ("\".m\"", "empty label"),
+    ] {
+        let content = format!("[\"example.com\"]\nsub = [\"www\", {sub}]\n");
+        let err = parse_with(&content, None, &ParseOptions::default()).unwrap_err();
+        assert!(
+            matches!(&err, RuleParseError::InvalidDomain { location, .. } if location.line == 2 && location.column == 15),
+            "{err}"
+        );
+        assert!(err.to_string().contains(message), "{err}");
+    }
+}
+
+#[test]
+fn test_malformed_values() {
+    for content in [
+        "[\"example.com\"]\nban = [1]\n",
+        "[\"example.com\"]\nban = \"utm_source\"\n",
+        "[\"example.com\"]\nsub = [[\"www\"]]\n",
+        "[\"example.com\"]\npost_hooks = [{ args = {} }]\n",
+        "\"example.com\" = 1\n",
+    ] {
+        let err = parse_with(content, None, &ParseOptions::default());
+        assert!(
+            matches!(err, Err(RuleParseError::Syntax { .. })),
+            "{content}"
+        );
+    }
+}
+
+#[test]
+fn test_lenient() {
+    let content = r#"
+["example.com"]
+ban = ["utm_source"]
+
+["bilibili.com"]
+ban = [
+  "vd_source",
+  "share_(",
+  { keys = ["spm_(", "from"], when_present = ["bvid"] },
+]
+
+["shop.test"]
+ban = ["fbclid"]
+tracker_allow = ["tracker.test"]
+
+["jd.com"]
+ban = [".*"]
+"#;
+    assert!(parse_with(content, None, &ParseOptions::default()).is_err());
+
+    let lenient = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    let (rules, warnings) = parse_with(content, None, &lenient).unwrap();
+    assert!(rules.hosts.contains_key("example.com"));
+    assert!(rules.hosts.contains_key("jd.com"));
+    assert!(!rules.hosts.contains_key("shop.test"));
+    let bilibili = &rules.hosts["bilibili.com"];
+    let patterns: Vec<_> = bilibili.rules.iter().map(|re| re.as_str()).collect();
+    assert_eq!(patterns, ["vd_source"]);
+    assert_eq!(bilibili.conditional[0].keys.len(), 1);
+
+    assert_eq!(warnings.len(), 3, "{warnings:?}");
+    assert!(matches!(
+        &warnings[0],
+        RuleWarning::SkippedPattern { domain, pattern, location, .. }
+            if domain == "bilibili.com" && pattern == "share_(" && location.line == 8
+    ));
+    assert!(matches!(
+        &warnings[1],
+        RuleWarning::SkippedPattern { pattern, .. } if pattern == "spm_("
+    ));
+    assert!(matches!(
+        &warnings[2],
+        RuleWarning::SkippedEntry { domain, location, .. }
+            if domain == "shop.test" && location.line == 12
+    ));
+    assert!(warnings[0]
+        .to_string()
+        .starts_with("8:3: skipped invalid regexp 'share_('"));
+
+    // syntax errors are not skipped
+    assert!(parse_with("[a\nban = 1", None, &lenient).is_err());
+}
+
+#[test]
+fn test_shared_regex() {
+    let (rules, _) = parse_with(
+        r#"
+["a.example"]
+ban = ["^utm_", "spm"]
+
+["b.example"]
+ban = ["fbclid", "^utm_"]
+keep = ["spm"]
+"#,
+        None,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    let (a, b) = (&rules.hosts["a.example"], &rules.hosts["b.example"]);
+    assert!(Arc::ptr_eq(&a.rules[0], &b.rules[1]));
+    assert!(Arc::ptr_eq(&a.rules[1], &b.keep[0]));
+    assert!(!Arc::ptr_eq(&a.rules[0], &b.rules[0]));
+}
+
+#[test]
+fn test_pattern_rules() {
+    let (rules, _) = parse_with(
+        r#"
+[pattern.'^https?://[^/]+\.page\.link/']
+redirect = true
+
+["example.com"]
+ban = ["utm_source"]
+
+[pattern.'^https://[^/]+/sharer\.php']
+drop_all_query = true
+
+[pattern.'^https://']
+ban = ["fbclid"]
+"#,
+        None,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(rules.hosts.len(), 1);
+    let patterns: Vec<_> = rules.patterns.iter().map(|(re, _)| re.as_str()).collect();
+    assert_eq!(
+        patterns,
+        [
+            r"^https?://[^/]+\.page\.link/",
+            r"^https://[^/]+/sharer\.php",
+            "^https://"
+        ]
+    );
+
+    let find = |url: &str| {
+        rules
+            .find(&url::Url::parse(url).unwrap())
+            .map(|(key, _)| key)
+    };
+    assert_eq!(find("https://example.com/?fbclid=1"), Some("example.com"));
+    assert_eq!(
+        find("https://foo.page.link/abc"),
+        Some(r"^https?://[^/]+\.page\.link/")
+    );
+    assert_eq!(
+        find("https://m.facebook.com/sharer.php?u=x"),
+        Some(r"^https://[^/]+/sharer\.php")
+    );
+    assert_eq!(find("http://other.test/"), None);
+
+    let err = parse_with(
+        "[pattern.'^https://']\nsub = [\"www\"]\n",
+        None,
+        &ParseOptions::default(),
+    );
+    assert!(matches!(err, Err(RuleParseError::InvalidDomain { .. })));
+    let err = parse_with("[pattern.'(']\n", None, &ParseOptions::default());
+    assert!(
+        matches!(err, Err(RuleParseError::InvalidRegex { location, .. }) if location.column == 10)
+    );
+}
+
+#[test]
+fn test_scheme_rules() {
+    let (rules, _) = parse_with(
+        r#"
+["scheme:Magnet"]
+tracker_deny = [".evil.example"]
+"#,
+        None,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    let magnet = &rules.hosts["scheme:magnet"];
+    assert_eq!(magnet.keep[0].as_str(), "^(?:xt|dn)$");
+    let trackers = magnet.trackers.as_ref().unwrap();
+    assert!(trackers.rejects("tr=udp%3A%2F%2Ftracker.evil.example%3A80"));
+    assert!(!trackers.rejects("tr=udp%3A%2F%2Fopentrackr.org%3A1337"));
+    assert!(!trackers.rejects("dn=evil.example"));
+
+    for (rule, message) in [
+        (
+            "[\"example.com\"]\ntracker_allow = [\"a.org\"]",
+            "`tracker_allow` and `tracker_deny` only apply to `scheme:magnet`",
+        ),
+        (
+            "[\"scheme:mailto\"]\nsub = [\"www\"]",
+            "`sub` and `include_bare` don't apply to scheme rules",
+        ),
+    ] {
+        let err = parse_with(rule, None, &ParseOptions::default()).unwrap_err();
+        assert!(
+            matches!(&err, RuleParseError::InvalidDomain { message: m, .. } if m == message),
+            "{err}"
+        );
+    }
+}
+
+#[test]
+fn test_ruleset_diff() {
+    let parse = |content: &str| {
+        parse_with(content, None, &ParseOptions::default())
+            .unwrap()
+            .0
+    };
+    let old = parse(
+        r#"
+["a.com"]
+ban = ["x", "y"]
+
+["b.com"]
+ban = ["z"]
+"#,
+    );
+    let new = parse(
+        r#"
+["a.com"]
+ban = ["x", "w", "v"]
+
+["c.com"]
+ban = ["u"]
+
+[pattern.'^https://d\.com/']
+ban = ["t"]
+"#,
+    );
+    let diff = RulesetDiff::between(&old, &new);
+    assert_eq!(
+        diff,
+        RulesetDiff {
+            domains_added: 2,
+            domains_removed: 1,
+            patterns_added: 4,
+            patterns_removed: 2,
+        }
+    );
+    assert_eq!(
+        diff.to_string(),
+        "+2 domains, -1 domain, +4 patterns, -2 patterns"
+    );
+
+    let diff = RulesetDiff::between(&old, &old);
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "no change");
+}
+
+#[test]
+fn test_pattern_set_fallback() {
+    let (mut rules, _) = parse_with(
+        "[pattern.'^https://a\\.test/']\n[pattern.'^https://']\n",
+        None,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    let url = url::Url::parse("https://b.test/").unwrap();
+    assert_eq!(rules.find(&url).map(|(key, _)| key), Some("^https://"));
+
+    // patterns added after the set is built are still consulted
+    let rule = Arc::clone(&rules.patterns[0].1);
+    rules.patterns.insert(
+        0,
+        (compile_regex("^https://b\\.test/", false).unwrap(), rule),
+    );
+    assert_eq!(
+        rules.find(&url).map(|(key, _)| key),
+        Some("^https://b\\.test/")
+    );
 }
@@ -0,0 +1,156 @@
+//! Cleaning as a [`tower::Service`], to compose with other middleware.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tower::{BoxError, Layer, Service};
+use url::Url;
+
+use crate::{ClearOutcome, UrlCleanError, UrlCleaner};
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// A [`Service`] cleaning each URL it is called with, like [`UrlCleaner::try_clear`].
+///
+/// It is always ready: redirections are not limited by the cleaner itself, so wrap it with
+/// `tower::limit::ConcurrencyLimit` to bound them and propagate backpressure.
+#[derive(Clone)]
+pub struct CleanService {
+    cleaner: Arc<UrlCleaner>,
+}
+
+impl CleanService {
+    pub fn new(cleaner: Arc<UrlCleaner>) -> Self {
+        Self { cleaner }
+    }
+}
+
+impl Service<String> for CleanService {
+    type Response = ClearOutcome;
+    type Error = UrlCleanError;
+    type Future = BoxFuture<Result<ClearOutcome, UrlCleanError>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, url: String) -> Self::Future {
+        let cleaner = Arc::clone(&self.cleaner);
+        Box::pin(async move { cleaner.try_clear(&url).await })
+    }
+}
+
+/// A [`Layer`] cleaning the URL before passing it to the inner service, which receives a
+/// [`Url`]. URLs without any matching rule are passed as is; other errors of the cleaner
+/// fail the request.
+#[derive(Clone)]
+pub struct CleanLayer {
+    cleaner: Arc<UrlCleaner>,
+}
+
+impl CleanLayer {
+    pub fn new(cleaner: Arc<UrlCleaner>) -> Self {
+        Self { cleaner }
+    }
+}
+
+impl<S> Layer<S> for CleanLayer {
+    type Service = Clean<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Clean {
+            cleaner: Arc::clone(&self.cleaner),
+            inner,
+        }
+    }
+}
+
+/// The service built by [`CleanLayer`].
+#[derive(Clone)]
+pub struct Clean<S> {
+    cleaner: Arc<UrlCleaner>,
+    inner: S,
+}
+
+impl<S> Service<String> for Clean<S>
+where
+    S: Service<Url> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<S::Response, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, url: String) -> Self::Future {
+        let cleaner = Arc::clone(&self.cleaner);
+        // the ready service must be the one called, leave the clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let url = match cleaner.try_clear(&url).await {
+                Ok(outcome) => outcome.into_url(),
+                Err(UrlCleanError::NoMatchRule) => Url::parse(&url)?,
+                Err(err) => return Err(err.into()),
+            };
+            inner.call(url).await.map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(test)]
+const RULES: &str = r#"
+["example.com"]
+ban = ["utm_source"]
+"#;
+
+#[tokio::test]
+async fn test_clean_service() {
+    use tower::ServiceExt;
+
+    let service = CleanService::new(Arc::new(UrlCleaner::from_toml(RULES).unwrap()));
+    let outcome = service
+        .clone()
+        .oneshot("https://example.com/?utm_source=a&id=1".to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        outcome,
+        ClearOutcome::Cleaned(Url::parse("https://example.com/?id=1").unwrap())
+    );
+    let outcome = service
+        .oneshot("https://example.com/?id=1".to_string())
+        .await
+        .unwrap();
+    assert!(!outcome.is_cleaned());
+}
+
+#[tokio::test]
+async fn test_clean_layer() {
+    use tower::ServiceExt;
+
+    let cleaner = Arc::new(UrlCleaner::from_toml(RULES).unwrap());
+    let echo = tower::service_fn(|url: Url| async move { Ok::<_, BoxError>(url.to_string()) });
+    let service = CleanLayer::new(cleaner).layer(echo);
+    for (input, expected) in [
+        (
+            "https://example.com/?utm_source=a&id=1",
+            "https://example.com/?id=1",
+        ),
+        ("https://example.com/?id=1", "https://example.com/?id=1"),
+        (
+            "https://unknown.test/?utm_source=a",
+            "https://unknown.test/?utm_source=a",
+        ),
+    ] {
+        let output = service.clone().oneshot(input.to_string()).await.unwrap();
+        assert_eq!(output, expected);
+    }
+    assert!(service.oneshot("not a url".to_string()).await.is_err());
+}
@@ -0,0 +1,131 @@
+//! Heuristics flagging the query parameters left after cleaning that look like tracking, to
+//! help improving the ruleset. Nothing is ever removed because of them.
+
+use url::Url;
+
+/// A parameter kept in the cleaned URL that looks like tracking, reported in
+/// [`ClearReport::suspicious`](crate::ClearReport::suspicious) when enabled with
+/// [`UrlCleanerBuilder::flag_suspicious_params`](crate::UrlCleanerBuilder::flag_suspicious_params).
+#[derive(Clone, Debug, PartialEq)]
+pub struct SuspiciousParam {
+    pub key: String,
+    pub reason: SuspicionReason,
+    /// Shannon entropy of the decoded value, in bits per character.
+    pub entropy: f64,
+}
+
+// the entropy is computed from a non empty value or is zero, it is never NaN
+impl Eq for SuspiciousParam {}
+
+/// Why a parameter is flagged, the first matching heuristic wins.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SuspicionReason {
+    /// The value is a UUID, like `3f2504e0-4f89-11d3-9a0c-0305e82c3301`.
+    Uuid,
+    /// The key looks like the ones of trackers, ending with `_id`, `clid` or `_token`.
+    TrackerKey,
+    /// The value is over 20 characters of base64 or hex, mixing character classes with
+    /// high entropy, like an encoded blob or a token.
+    HighEntropy,
+}
+
+/// Minimal length of a value to be considered a blob.
+const MIN_BLOB_LEN: usize = 21;
+/// Minimal entropy of a blob, in bits per character. English words stay below.
+const MIN_BLOB_ENTROPY: f64 = 3.5;
+const TRACKER_KEY_SUFFIXES: &[&str] = &["_id", "clid", "_token"];
+
+/// Flag the suspicious parameters of the query, in query order.
+pub(crate) fn scan(url: &Url) -> Vec<SuspiciousParam> {
+    url.query_pairs()
+        .filter_map(|(key, value)| {
+            let entropy = entropy(&value);
+            let reason = if is_uuid(&value) {
+                SuspicionReason::Uuid
+            } else if is_tracker_key(&key) {
+                SuspicionReason::TrackerKey
+            } else if is_blob(&value, entropy) {
+                SuspicionReason::HighEntropy
+            } else {
+                return None;
+            };
+            Some(SuspiciousParam {
+                key: key.into_owned(),
+                reason,
+                entropy,
+            })
+        })
+        .collect()
+}
+
+fn is_uuid(value: &str) -> bool {
+    value.len() == 36
+        && value.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+fn is_tracker_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    TRACKER_KEY_SUFFIXES
+        .iter()
+        .any(|suffix| key.len() > suffix.len() && key.ends_with(suffix))
+}
+
+fn is_blob(value: &str, entropy: f64) -> bool {
+    let base64 = |c: char| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=' | '-' | '_');
+    let classes = [
+        value.chars().any(|c| c.is_ascii_lowercase()),
+        value.chars().any(|c| c.is_ascii_uppercase()),
+        value.chars().any(|c| c.is_ascii_digit()),
+    ];
+    value.len() >= MIN_BLOB_LEN
+        && value.chars().all(base64)
+        && classes.iter().filter(|&&class| class).count() >= 2
+        && entropy >= MIN_BLOB_ENTROPY
+}
+
+/// Shannon entropy of the characters of the value, in bits per character.
+fn entropy(value: &str) -> f64 {
+    let mut counts = std::collections::HashMap::new();
+    for c in value.chars() {
+        *counts.entry(c).or_insert(0usize) += 1;
+    }
+    let len = value.chars().count() as f64;
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[test]
+fn test_scan() {
+    let url = Url::parse(
+        "https://example.com/?sid=3f2504e0-4f89-11d3-9a0c-0305e82c3301&page=2\
+         &blob=eyJ1c2VyIjoiYWxpY2UiLCJpZCI6MTIzNDU2Nzg5fQ%3D%3D&ref_id=7\
+         &q=how+to+clean+urls+in+rust&slug=a-very-long-article-title-here",
+    )
+    .unwrap();
+    let flagged: Vec<_> = scan(&url)
+        .into_iter()
+        .map(|param| (param.key, param.reason))
+        .collect();
+    assert_eq!(
+        flagged,
+        [
+            ("sid".to_string(), SuspicionReason::Uuid),
+            ("blob".to_string(), SuspicionReason::HighEntropy),
+            ("ref_id".to_string(), SuspicionReason::TrackerKey),
+        ]
+    );
+
+    let blob = &scan(&url)[1];
+    assert!(blob.entropy > MIN_BLOB_ENTROPY, "{}", blob.entropy);
+    assert_eq!(entropy("2"), 0.0);
+    assert_eq!(entropy("ab"), 1.0);
+}
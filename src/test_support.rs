@@ -0,0 +1,195 @@
+//! Fixtures for the tests of crates built on clearurl, so redirections can be exercised
+//! without reaching the network.
+//!
+//! ```no_run
+//! # async fn run() {
+//! use clearurl::{test_support::MockShortener, UrlCleaner};
+//!
+//! let shortener = MockShortener::new().await;
+//! shortener.map("/C0lw13z", "https://www.bilibili.com/video/BV1GJ411x7h7?p=1");
+//! let cleaner = UrlCleaner::builder()
+//!     .rules_from_toml(&shortener.rules_toml())
+//!     .http_client(shortener.client())
+//!     .build()
+//!     .unwrap();
+//! let url = cleaner.clear(&shortener.url("/C0lw13z")).await.unwrap();
+//! assert_eq!(url.as_str(), "https://www.bilibili.com/video/BV1GJ411x7h7?p=1");
+//! # }
+//! ```
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::mock::{MockServer, Route};
+
+/// Host name the shortener is reached with, unless given to [`MockShortener::with_host`].
+pub const DEFAULT_HOST: &str = "short.test";
+
+/// A shortener served on a local port, answering each mapped path with a redirection to its
+/// target. Unmapped paths get a 404. It runs on the current Tokio runtime.
+pub struct MockShortener {
+    server: MockServer,
+    host: String,
+}
+
+impl MockShortener {
+    /// Start a shortener reached as [`DEFAULT_HOST`].
+    pub async fn new() -> Self {
+        Self::with_host(DEFAULT_HOST).await
+    }
+
+    /// Start a shortener reached with the given host name.
+    pub async fn with_host(host: &str) -> Self {
+        Self {
+            server: MockServer::start(Vec::new()).await,
+            host: host.to_string(),
+        }
+    }
+
+    /// Redirect the path, query included, to the target with a 302. A path prefixed with a
+    /// method, like `HEAD /C0lw13z`, is only redirected for requests with this method.
+    pub fn map(&self, path: &str, target: &str) -> &Self {
+        self.server.route(path, Route::redirect(target));
+        self
+    }
+
+    /// Answer the path with a 200 and the given body, like the HTML pages of shorteners
+    /// redirecting with a `<meta http-equiv="refresh">`.
+    pub fn page(&self, path: &str, body: &str) -> &Self {
+        self.server.route(path, Route::ok(body));
+        self
+    }
+
+    /// Answer the path with the given status, without redirection.
+    pub fn status(&self, path: &str, status: u16) -> &Self {
+        self.server.route(
+            path,
+            Route {
+                status,
+                ..Default::default()
+            },
+        );
+        self
+    }
+
+    /// Answer HEAD requests for the path with a 405, as some shorteners do, so the
+    /// redirection is only given to GET requests.
+    pub fn reject_head(&self, path: &str) -> &Self {
+        self.server.route(
+            &format!("HEAD {path}"),
+            Route {
+                status: 405,
+                ..Default::default()
+            },
+        );
+        self
+    }
+
+    /// Answer the path with a 429 asking to retry after the given delay.
+    pub fn rate_limit(&self, path: &str, retry_after: Duration) -> &Self {
+        self.server.route(
+            path,
+            Route {
+                status: 429,
+                headers: vec![("Retry-After".to_string(), retry_after.as_secs().to_string())],
+                ..Default::default()
+            },
+        );
+        self
+    }
+
+    /// Wait before answering the path, mapped beforehand.
+    pub fn latency(&self, path: &str, delay: Duration) -> &Self {
+        self.server.update_route(path, |route| route.delay = delay);
+        self
+    }
+
+    /// Host name of the short links, resolved to [`MockShortener::addr`] by
+    /// [`MockShortener::client`].
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Local address the shortener listens on. Its port is part of every short link.
+    pub fn addr(&self) -> SocketAddr {
+        self.server.addr()
+    }
+
+    /// The short link of the path, like `http://short.test:40123/C0lw13z`.
+    pub fn url(&self, path: &str) -> String {
+        self.server.url(&self.host, path)
+    }
+
+    /// A client reaching the shortener by its host name. It doesn't follow redirections,
+    /// the cleaner reads the target from the response, so targets are never requested.
+    pub fn client(&self) -> reqwest::Client {
+        self.client_with(&[])
+    }
+
+    /// A client reaching the shortener and the given ones, for short links pointing to
+    /// other shorteners.
+    pub fn client_with(&self, others: &[&MockShortener]) -> reqwest::Client {
+        (others.iter())
+            .fold(
+                reqwest::Client::builder()
+                    .no_proxy()
+                    .redirect(reqwest::redirect::Policy::none())
+                    .resolve(&self.host, self.server.addr()),
+                |builder, other| builder.resolve(&other.host, other.server.addr()),
+            )
+            .build()
+            .unwrap_or_default()
+    }
+
+    /// A ruleset asking for the redirection of the links of the shortener, to use alone or
+    /// append to another ruleset.
+    pub fn rules_toml(&self) -> String {
+        format!("[\"{}\"]\nredirect = true\n", self.host)
+    }
+
+    /// `METHOD path` of every request received, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.server.requests()
+    }
+}
+
+#[tokio::test]
+async fn test_mock_shortener() {
+    use crate::{UrlCleanError, UrlCleaner};
+
+    let shortener = MockShortener::new().await;
+    shortener
+        .map(
+            "/C0lw13z",
+            "https://www.bilibili.com/video/BV1GJ411x7h7?p=1&share_source=COPY",
+        )
+        .map("/head", "https://example.com/?utm_source=x&id=1")
+        .reject_head("/head")
+        .rate_limit("/busy", Duration::from_secs(60));
+    let rules = format!(
+        "{}\n{}",
+        shortener.rules_toml(),
+        "[\"default\"]\nban = [\"utm_source\", \"share_source\"]\n"
+    );
+    let cleaner = UrlCleaner::builder()
+        .rules_from_toml(&rules)
+        .http_client(shortener.client())
+        .build()
+        .unwrap();
+
+    let url = cleaner.clear(&shortener.url("/C0lw13z")).await.unwrap();
+    assert_eq!(
+        url.as_str(),
+        "https://www.bilibili.com/video/BV1GJ411x7h7?p=1"
+    );
+    let url = cleaner.clear(&shortener.url("/head")).await.unwrap();
+    assert_eq!(url.as_str(), "https://example.com/?id=1");
+    assert!(matches!(
+        cleaner.clear(&shortener.url("/busy")).await,
+        Err(UrlCleanError::HostCoolingDown { .. })
+    ));
+    assert_eq!(
+        shortener.requests(),
+        ["HEAD /C0lw13z", "HEAD /head", "GET /head", "HEAD /busy"]
+    );
+}
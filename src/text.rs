@@ -0,0 +1,260 @@
+//! Find URLs in free text, like a chat message.
+
+use std::ops::Range;
+
+/// Byte ranges of the `http`/`https` URLs in the text, in order. The URL of a markdown link
+/// `[label](url)` ends at its closing parenthesis; a bare URL ends at whitespace, without
+/// trailing punctuation or an unbalanced closing parenthesis. URLs enclosed in angle
+/// brackets `<url>` are skipped, as the common convention for "don't touch this link".
+pub(crate) fn find_urls(text: &str) -> Vec<Range<usize>> {
+    let mut urls = Vec::new();
+    let mut cursor = 0;
+    while let Some(start) = next_scheme(text, cursor) {
+        let markdown = text[..start].ends_with("](");
+        let rest = &text[start..];
+        let mut depth = 0_usize;
+        let mut end = rest.len();
+        for (i, c) in rest.char_indices() {
+            match c {
+                c if c.is_whitespace() || matches!(c, '<' | '>' | '"') => {
+                    end = i;
+                    break;
+                }
+                '(' => depth += 1,
+                ')' if depth == 0 && markdown => {
+                    end = i;
+                    break;
+                }
+                ')' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        let mut url = &rest[..end];
+        if !markdown {
+            url = trim_trailing(url);
+        }
+        let enclosed = text[..start].ends_with('<') && rest[end..].starts_with('>');
+        if url.len() > "https://".len() && !enclosed {
+            urls.push(start..start + url.len());
+        }
+        cursor = start + end.max(1);
+    }
+    urls
+}
+
+fn next_scheme(text: &str, from: usize) -> Option<usize> {
+    let rest = &text[from..];
+    let http = rest.find("http://");
+    let https = rest.find("https://");
+    let offset = match (http, https) {
+        (Some(a), Some(b)) => a.min(b),
+        (a, b) => a.or(b)?,
+    };
+    Some(from + offset)
+}
+
+/// Strip the punctuation ending a sentence, and the closing parenthesis that is not part of
+/// the URL like in `(see https://example.com)`.
+fn trim_trailing(mut url: &str) -> &str {
+    loop {
+        let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '*', '_']);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(inner) if inner.matches('(').count() <= inner.matches(')').count() => inner,
+            _ => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+/// The entry of `deny` matching the host of an `http`/`https` URL embedded in the value of
+/// the raw `key=value` pair. The value is decoded up to twice, since redirectors often
+/// encode the target again. A value merely mentioning a domain, without scheme, is fine.
+pub(crate) fn denied_value_host<'d>(pair: &str, deny: &'d [String]) -> Option<&'d str> {
+    if deny.is_empty() {
+        return None;
+    }
+    let (_, value) = pair.split_once('=')?;
+    // Both `http://` and its encoded form `http%3A%2F%2F` contain `http`, check it before
+    // decoding anything
+    if !value
+        .as_bytes()
+        .windows(4)
+        .any(|w| w.eq_ignore_ascii_case(b"http"))
+    {
+        return None;
+    }
+
+    let mut value = percent_decode(value);
+    for _ in 0..2 {
+        for range in find_urls(&value) {
+            let Ok(url) = url::Url::parse(&value[range]) else {
+                continue;
+            };
+            let Some(host) = url.host_str() else {
+                continue;
+            };
+            let hit = deny.iter().find(|denied| is_subdomain(host, denied));
+            if hit.is_some() {
+                return hit.map(String::as_str);
+            }
+        }
+        if !value.contains('%') {
+            break;
+        }
+        value = percent_decode(&value);
+    }
+    None
+}
+
+/// Whether the host is the domain itself or one of its subdomains.
+pub(crate) fn is_subdomain(host: &str, domain: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    host.strip_suffix(domain)
+        .is_some_and(|rest| rest.is_empty() || rest.ends_with('.'))
+}
+
+/// The domain of the URL to look rules up with. A fully qualified `example.com.` resolves
+/// like `example.com`, so the trailing dot is ignored.
+pub(crate) fn lookup_domain(url: &url::Url) -> Option<&str> {
+    url.domain()
+        .map(|domain| domain.strip_suffix('.').unwrap_or(domain))
+}
+
+/// Decode one level of `application/x-www-form-urlencoded` value. Unlike
+/// `form_urlencoded::parse`, it doesn't split on `&` and `=`, which may appear once decoded.
+pub(crate) fn percent_decode(value: &str) -> String {
+    String::from_utf8_lossy(&decode_bytes(value, true)).into_owned()
+}
+
+/// Decode every percent escape of the value, whatever the case of its hex digits. Invalid
+/// escapes are kept as is, and `+` becomes a space only when `plus_as_space` is set.
+pub(crate) fn decode_bytes(value: &str, plus_as_space: bool) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // `from_str_radix` alone would also take a sign, like in `%+F`
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) if plus_as_space => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// Replace every URL in the text with the output of `f`, keeping it when `f` returns `None`.
+pub(crate) fn replace_urls(text: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut last = 0;
+    for range in find_urls(text) {
+        output.push_str(&text[last..range.start]);
+        match f(&text[range.clone()]) {
+            Some(replaced) => output.push_str(&replaced),
+            None => output.push_str(&text[range.clone()]),
+        }
+        last = range.end;
+    }
+    output.push_str(&text[last..]);
+    output
+}
+
+#[cfg(test)]
+fn urls(text: &str) -> Vec<&str> {
+    find_urls(text)
+        .into_iter()
+        .map(|range| &text[range])
+        .collect()
+}
+
+#[test]
+fn test_find_urls() {
+    assert_eq!(
+        urls("see https://example.com/a?b=1, and (https://example.com/c)."),
+        ["https://example.com/a?b=1", "https://example.com/c"]
+    );
+    assert_eq!(
+        urls("[Rust](https://en.wikipedia.org/wiki/Rust_(programming_language)?utm_source=x) ok"),
+        ["https://en.wikipedia.org/wiki/Rust_(programming_language)?utm_source=x"]
+    );
+    assert_eq!(
+        urls("https://en.wikipedia.org/wiki/Rust_(programming_language)!"),
+        ["https://en.wikipedia.org/wiki/Rust_(programming_language)"]
+    );
+    assert_eq!(
+        urls("[a](https://a.example/?x=1)https://b.example/"),
+        ["https://a.example/?x=1", "https://b.example/"]
+    );
+    assert!(urls("no url, only https:// here").is_empty());
+    assert_eq!(
+        urls("keep <https://a.example/?utm_source=x> but https://b.example/?utm_source=y"),
+        ["https://b.example/?utm_source=y"]
+    );
+    assert_eq!(
+        urls("<https://a.example/ https://b.example/>"),
+        ["https://a.example/", "https://b.example/"]
+    );
+}
+
+#[test]
+fn test_denied_value_host() {
+    let deny = ["doubleclick.net".to_string(), "adnxs.com".to_string()];
+    assert_eq!(
+        denied_value_host(
+            "r=https%3A%2F%2Fad.doubleclick.net%2Fddm%2Fclk%3Fid%3D1",
+            &deny
+        ),
+        Some("doubleclick.net")
+    );
+    // encoded twice by a redirector
+    assert_eq!(
+        denied_value_host("r=https%253A%252F%252Fib.adnxs.com%252Fclick", &deny),
+        Some("adnxs.com")
+    );
+    assert_eq!(
+        denied_value_host("q=doubleclick.net+is+an+ad+network", &deny),
+        None
+    );
+    assert_eq!(
+        denied_value_host("q=about+http+and+doubleclick.net", &deny),
+        None
+    );
+    assert_eq!(
+        denied_value_host("r=https%3A%2F%2Fnotdoubleclick.net%2F", &deny),
+        None
+    );
+    assert_eq!(
+        denied_value_host("r=https%3A%2F%2Fexample.com%2F", &deny),
+        None
+    );
+    assert_eq!(percent_decode("a%2Bb+c%3D%zz"), "a+b c=%zz");
+}
+
+#[test]
+fn test_decode_bytes() {
+    assert_eq!(decode_bytes("a%2Fb%2fc", false), b"a/b/c");
+    assert_eq!(decode_bytes("a+b%20c", true), b"a b c");
+    assert_eq!(decode_bytes("a+b", false), b"a+b");
+    // invalid escapes are kept
+    for value in ["%+F", "%-1", "%zz", "%4", "%"] {
+        assert_eq!(decode_bytes(value, false), value.as_bytes(), "{value}");
+    }
+}
@@ -0,0 +1,88 @@
+//! Finding out a URL is already clean must not allocate beyond parsing it. The counting
+//! allocator is global, so this lives in its own test binary.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use clearurl::{ClearOutcome, ReportDetail, UrlCleaner};
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn test_already_clean_allocates_nothing() {
+    let cleaner = UrlCleaner::from_toml(
+        r#"
+[settings]
+default_is_global = true
+
+["default"]
+ban = ["utm_.*", "fbclid"]
+
+["example.com"]
+ban = ["spm", { keys = ["si"], when_present = ["share"] }]
+keep = ["spm_keep"]
+"#,
+    )
+    .unwrap();
+    let url = "https://example.com/watch?v=1&t=30&si=2&spm_keep=1";
+
+    // warm up the lazily initialized state of the ruleset and the regex caches
+    cleaner.try_clear_offline(url).unwrap();
+    let parse = allocations(|| drop(url::Url::parse(url).unwrap()));
+    let clear = allocations(|| {
+        let outcome = cleaner.try_clear_offline(url).unwrap();
+        assert!(matches!(outcome, ClearOutcome::AlreadyClean(_)));
+        drop(outcome);
+    });
+    assert_eq!(clear, parse);
+
+    // cleaning does allocate, so the counter works
+    let dirty = "https://example.com/watch?v=1&utm_source=a";
+    cleaner.try_clear_offline(dirty).unwrap();
+    assert!(allocations(|| drop(cleaner.try_clear_offline(dirty).unwrap())) > parse);
+}
+
+#[test]
+fn test_keys_only_copies_no_value() {
+    let cleaner = |detail| {
+        UrlCleaner::builder()
+            .rules_from_toml("[\"example.com\"]\nban = [\"utm_.*\"]\n")
+            .report_detail(detail)
+            .on_event(drop)
+            .build()
+            .unwrap()
+    };
+    let (keys_only, full) = (cleaner(ReportDetail::KeysOnly), cleaner(ReportDetail::Full));
+    let url = "https://example.com/?id=1&utm_source=a&utm_medium=b";
+    keys_only.try_clear_offline(url).unwrap();
+    full.try_clear_offline(url).unwrap();
+
+    // the full detail copies each of the two values once, the keys only detail never does
+    let keys_only = allocations(|| drop(keys_only.try_clear_offline(url).unwrap()));
+    let full = allocations(|| drop(full.try_clear_offline(url).unwrap()));
+    assert_eq!(full, keys_only + 2);
+}
@@ -0,0 +1,99 @@
+use clearurl::{Preset, RuleWarning, UrlCleanError, UrlCleaner};
+
+const YOUTUBE: &str = "https://www.youtube.com/watch?v=dQw4w9WgXcQ&feature=share&utm_source=x";
+const AMAZON: &str = "https://www.amazon.com/dp/B000000000?qid=1&crid=2&th=1";
+const BING: &str = "https://bing.com/search?q=rust&form=QBLH&cvid=1";
+
+fn clear(cleaner: &UrlCleaner, url: &str) -> Result<String, UrlCleanError> {
+    cleaner.clear_offline(url).map(|url| url.to_string())
+}
+
+/// Warnings of the bundled ruleset, except the hooks left out of this build.
+fn unexpected_warnings(cleaner: &UrlCleaner) -> Vec<&RuleWarning> {
+    (cleaner.warnings().iter())
+        .filter(|warning| !matches!(warning, RuleWarning::HookNotCompiled { .. }))
+        .collect()
+}
+
+#[test]
+fn test_default_rule_always_included() {
+    let cleaner = UrlCleaner::with_presets(&[]);
+    assert_eq!(
+        clear(&cleaner, "https://unknown.test/?utm_source=a&id=1").unwrap(),
+        "https://unknown.test/?id=1"
+    );
+    assert!(cleaner.rule_for("www.youtube.com").is_none());
+}
+
+#[test]
+fn test_social() {
+    let cleaner = UrlCleaner::with_presets(&[Preset::Social]);
+    assert_eq!(
+        clear(&cleaner, YOUTUBE).unwrap(),
+        "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+    );
+    assert!(cleaner.rule_for("www.amazon.com").is_none());
+    assert!(matches!(
+        clear(&cleaner, AMAZON),
+        Err(UrlCleanError::NothingToClear)
+    ));
+}
+
+#[test]
+fn test_ecommerce() {
+    let cleaner = UrlCleaner::with_presets(&[Preset::Ecommerce]);
+    assert_eq!(
+        clear(&cleaner, AMAZON).unwrap(),
+        "https://www.amazon.com/dp/B000000000"
+    );
+    assert_eq!(
+        clear(
+            &cleaner,
+            "https://www.ebay.com/itm/1234?_trkparms=a&_trksid=b&mkcid=1&var=5"
+        )
+        .unwrap(),
+        "https://www.ebay.com/itm/1234?var=5"
+    );
+    assert!(cleaner.rule_for("www.youtube.com").is_none());
+}
+
+#[test]
+fn test_everything() {
+    let cleaner = UrlCleaner::with_presets(&[Preset::Everything]);
+    assert_eq!(
+        clear(&cleaner, YOUTUBE).unwrap(),
+        "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+    );
+    assert_eq!(
+        clear(&cleaner, AMAZON).unwrap(),
+        "https://www.amazon.com/dp/B000000000"
+    );
+    assert_eq!(
+        clear(&cleaner, BING).unwrap(),
+        "https://bing.com/search?q=rust"
+    );
+    assert!(unexpected_warnings(&cleaner).is_empty());
+
+    // selecting a pack twice changes nothing
+    let combined = UrlCleaner::with_presets(&[Preset::Social, Preset::Everything]);
+    assert_eq!(combined.len(), cleaner.len());
+    assert!(unexpected_warnings(&combined).is_empty());
+}
+
+#[test]
+fn test_bundled_ruleset_file() {
+    // `rules.toml` is the whole bundled ruleset in one file, for `UrlCleaner::from_file`
+    let root = env!("CARGO_MANIFEST_DIR");
+    let packs: Vec<String> = ["default", "social", "ecommerce", "misc"]
+        .iter()
+        .map(|pack| std::fs::read_to_string(format!("{root}/rules/{pack}.toml")).unwrap())
+        .collect();
+    let bundled = std::fs::read_to_string(format!("{root}/rules.toml")).unwrap();
+    assert_eq!(bundled, packs.join("\n"));
+
+    let cleaner = UrlCleaner::from_file(&format!("{root}/rules.toml")).unwrap();
+    assert_eq!(
+        cleaner.len(),
+        UrlCleaner::with_presets(&[Preset::Everything]).len()
+    );
+}